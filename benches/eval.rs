@@ -0,0 +1,82 @@
+//! Compares the tree-walking evaluator, the bytecode VM and bit-parallel
+//! evaluation, evaluating one formula under many assignments to its variables.
+
+use std::hint::black_box;
+
+use blambda::cli::batch::truth_table_bits;
+use blambda::cli::compile::{compile, Vm};
+use blambda::cli::eval::{evaluate_expr_in, truth_table};
+use blambda::cli::parse::{fallible_parse, parse_expr, Rule};
+use blambda::{Env, Expr};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const VARS: usize = 16;
+const ASSIGNMENTS: u32 = 1024;
+
+/// A chain of ternary clauses mixing every kind of operator over `VARS`
+/// variables.
+fn formula() -> Expr {
+    let source = (0..VARS)
+        .map(|i| {
+            format!(
+                "(v{} & ~v{} | v{} ^ v{} ? v{} -> v{} : ~v{})",
+                i,
+                (i + 3) % VARS,
+                (i + 5) % VARS,
+                (i + 7) % VARS,
+                (i + 1) % VARS,
+                (i + 9) % VARS,
+                (i + 11) % VARS
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" <-> ");
+    let mut pairs = fallible_parse(Rule::expr, &source).unwrap();
+    parse_expr(pairs.next().unwrap().into_inner()).unwrap()
+}
+
+fn inputs(row: u32) -> Vec<bool> {
+    (0..VARS).map(|i| row >> i & 1 == 1).collect()
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let expr = formula();
+    let code = compile(&expr);
+    let rows: Vec<Vec<bool>> = (0..ASSIGNMENTS).map(inputs).collect();
+
+    let mut group = c.benchmark_group("eval");
+    group.bench_function("tree walker", |b| {
+        b.iter(|| {
+            rows.iter()
+                .filter(|row| {
+                    let env: Env = code.vars.iter().cloned().zip(row.iter().copied()).collect();
+                    evaluate_expr_in(black_box(&expr), &env).unwrap()
+                })
+                .count()
+        })
+    });
+    group.bench_function("bytecode vm", |b| {
+        let mut vm = Vm::new();
+        b.iter(|| {
+            rows.iter()
+                .filter(|row| vm.run(black_box(&code), row))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn bench_truth_table(c: &mut Criterion) {
+    let expr = formula();
+
+    let mut group = c.benchmark_group("truth table");
+    group.sample_size(10);
+    group.bench_function("bytecode vm", |b| b.iter(|| truth_table(black_box(&expr))));
+    group.bench_function("bit-parallel", |b| {
+        b.iter(|| truth_table_bits(black_box(&expr)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_eval, bench_truth_table);
+criterion_main!(benches);
@@ -0,0 +1,324 @@
+//! The `blambda!` macro, which parses a blambda expression at compile time.
+//!
+//! Expanded code refers to the `blambda` crate, which must be a dependency of
+//! the calling crate as well.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use blambda::{BinOp, Expr, UnOp, Visit};
+use proc_macro2::{Delimiter, Ident, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+
+/// Parses a blambda expression when the calling crate is compiled.
+///
+/// On its own, the macro expands to the `blambda::Expr` of the expression:
+///
+/// ```
+/// use blambda_macros::blambda;
+///
+/// let expr: blambda::Expr = blambda!(a & ~b | c);
+/// assert_eq!(expr, "a & ~b | c".parse().unwrap());
+/// ```
+///
+/// Followed by a binding of every variable to a Rust `bool` expression, it
+/// instead expands to Rust code that evaluates the expression directly. Each
+/// binding is evaluated exactly once, in order:
+///
+/// ```
+/// use blambda_macros::blambda;
+///
+/// let (x, flag) = (4, false);
+/// assert!(blambda!(a -> b ? t : c, a = x > 3, b = !flag, c = x % 2 == 1));
+/// ```
+///
+/// Only ASCII and keyword spellings of the operators are available, since the
+/// Unicode ones are not Rust tokens. Parse errors are compile errors pointing
+/// at the offending token:
+///
+/// ```compile_fail
+/// use blambda_macros::blambda;
+///
+/// let expr = blambda!(a & | b);
+/// ```
+///
+/// ```compile_fail
+/// use blambda_macros::blambda;
+///
+/// // `b` is not bound
+/// let value = blambda!(a & b, a = true);
+/// ```
+#[proc_macro]
+pub fn blambda(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    match expand(input.into()) {
+        Ok(tokens) => tokens,
+        Err((message, span)) => quote_spanned!(span=> ::std::compile_error!(#message)),
+    }
+    .into()
+}
+
+type MacroResult<T> = Result<T, (String, Span)>;
+
+/// The blambda source of the macro input, remembering which token each byte
+/// came from.
+#[derive(Default)]
+struct Source {
+    text: String,
+    tokens: Vec<(Range<usize>, Span)>,
+}
+
+impl Source {
+    fn push(&mut self, text: &str, span: Span, joint: bool) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.tokens.push((start..self.text.len(), span));
+        if !joint {
+            self.text.push(' ');
+        }
+    }
+
+    fn extend(&mut self, tokens: &[TokenTree]) -> MacroResult<()> {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                    self.push("(", group.span_open(), false);
+                    self.extend(&group.stream().into_iter().collect::<Vec<_>>())?;
+                    self.push(")", group.span_close(), false);
+                }
+                // the invisible groups around `macro_rules!` fragments
+                TokenTree::Group(group) if group.delimiter() == Delimiter::None => {
+                    self.extend(&group.stream().into_iter().collect::<Vec<_>>())?
+                }
+                TokenTree::Group(group) => {
+                    return Err((
+                        "only parentheses can group blambda expressions".to_string(),
+                        group.span(),
+                    ))
+                }
+                TokenTree::Punct(punct) => self.push(
+                    &punct.as_char().to_string(),
+                    punct.span(),
+                    punct.spacing() == proc_macro2::Spacing::Joint,
+                ),
+                token => self.push(&token.to_string(), token.span(), false),
+            }
+        }
+        Ok(())
+    }
+
+    /// The span of the token at or before byte `offset`.
+    fn span_at(&self, offset: usize) -> Span {
+        self.tokens
+            .iter()
+            .rev()
+            .find(|(range, _)| range.start <= offset)
+            .or(self.tokens.first())
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+
+    fn span_of_var(&self, name: &str) -> Span {
+        self.tokens
+            .iter()
+            .find(|(range, _)| &self.text[range.clone()] == name)
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+}
+
+fn expand(input: TokenStream) -> MacroResult<TokenStream> {
+    // the expression, then comma-separated `name = value` bindings
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut segments =
+        tokens.split(|token| matches!(token, TokenTree::Punct(p) if p.as_char() == ','));
+    let mut source = Source::default();
+    source.extend(segments.next().unwrap_or_default())?;
+
+    let expr: Expr = source
+        .text
+        .parse()
+        .map_err(|error: blambda::BlambdaError| match error.location() {
+            Some(location) => (location.reason.clone(), source.span_at(location.span.start)),
+            None => (error.to_string(), Span::call_site()),
+        })?;
+
+    let segments: Vec<&[TokenTree]> = segments.filter(|segment| !segment.is_empty()).collect();
+    if segments.is_empty() {
+        return Ok(build_expr(&expr));
+    }
+
+    let mut bindings: HashMap<String, Ident> = HashMap::new();
+    let mut names = Vec::new();
+    let mut lets = Vec::new();
+    for segment in segments {
+        let (name, value) = match segment {
+            [TokenTree::Ident(name), TokenTree::Punct(eq), value @ ..]
+                if eq.as_char() == '=' && !value.is_empty() =>
+            {
+                (name, value.iter().cloned().collect::<TokenStream>())
+            }
+            _ => {
+                return Err((
+                    "expected a binding of the form `name = value`".to_string(),
+                    segment[0].span(),
+                ))
+            }
+        };
+        let var = format_ident!("{}", name.to_string(), span = Span::mixed_site());
+        if bindings.insert(name.to_string(), var.clone()).is_some() {
+            return Err((format!("`{}` is bound more than once", name), name.span()));
+        }
+        names.push(name);
+        lets.push(quote_spanned!(value_span(&value)=> let #var: bool = #value;));
+    }
+    let mut vars = Vars::default();
+    vars.walk(&expr);
+    if vars.unknown {
+        let span = source
+            .tokens
+            .iter()
+            .find(|(range, _)| {
+                let text = source.text[range.clone()].to_lowercase();
+                text == "u" || text == "unknown"
+            })
+            .map_or_else(Span::call_site, |(_, span)| *span);
+        return Err((
+            "`u` has no value in Rust; only unbound expressions may use it".to_string(),
+            span,
+        ));
+    }
+    let vars = vars.names;
+    if let Some(name) = vars.iter().find(|name| !bindings.contains_key(*name)) {
+        return Err((
+            format!("`{}` is not bound to a value", name),
+            source.span_of_var(name),
+        ));
+    }
+    if let Some(name) = names.iter().find(|name| !vars.contains(&name.to_string())) {
+        return Err((
+            format!("`{}` does not appear in the expression", name),
+            name.span(),
+        ));
+    }
+    let value = eval_expr(&expr, &bindings);
+    Ok(quote!({
+        #(#lets)*
+        #value
+    }))
+}
+
+/// The free variables of an expression in order of first appearance, and
+/// whether it contains `u`.
+#[derive(Default)]
+struct Vars {
+    names: Vec<String>,
+    unknown: bool,
+}
+
+impl Visit for Vars {
+    fn visit_unknown(&mut self) {
+        self.unknown = true;
+    }
+
+    fn visit_var(&mut self, name: &str) {
+        if !self.names.iter().any(|var| var == name) {
+            self.names.push(name.to_string());
+        }
+    }
+}
+
+fn value_span(value: &TokenStream) -> Span {
+    value
+        .clone()
+        .into_iter()
+        .next()
+        .map_or_else(Span::call_site, |token| token.span())
+}
+
+fn bin_op(op: BinOp) -> TokenStream {
+    match op {
+        BinOp::Or => quote!(Or),
+        BinOp::And => quote!(And),
+        BinOp::Xor => quote!(Xor),
+        BinOp::Nand => quote!(Nand),
+        BinOp::Nor => quote!(Nor),
+        BinOp::Implies => quote!(Implies),
+        BinOp::Iff => quote!(Iff),
+    }
+}
+
+/// Code constructing `expr` as a `blambda::Expr`.
+fn build_expr(expr: &Expr) -> TokenStream {
+    let arc = |expr: &Expr| {
+        let expr = build_expr(expr);
+        quote!(::std::sync::Arc::new(#expr))
+    };
+    match expr {
+        Expr::Value(v) => quote!(::blambda::Expr::Value(#v)),
+        Expr::Unknown => quote!(::blambda::Expr::Unknown),
+        Expr::Var(name) => quote!(::blambda::Expr::Var(::std::string::String::from(#name))),
+        Expr::Unary { op, arg } => match op {
+            UnOp::Not => {
+                let arg = arc(arg);
+                quote!(::blambda::Expr::Unary { op: ::blambda::UnOp::Not, arg: #arg })
+            }
+        },
+        Expr::Binary { op, arg1, arg2 } => {
+            let (op, arg1, arg2) = (bin_op(*op), arc(arg1), arc(arg2));
+            quote!(::blambda::Expr::Binary { op: ::blambda::BinOp::#op, arg1: #arg1, arg2: #arg2 })
+        }
+        Expr::And(args) => {
+            let args = args.iter().map(build_expr);
+            quote!(::blambda::Expr::And(::std::vec![#(#args),*]))
+        }
+        Expr::Or(args) => {
+            let args = args.iter().map(build_expr);
+            quote!(::blambda::Expr::Or(::std::vec![#(#args),*]))
+        }
+        Expr::Ternary { cond, then, else_ } => {
+            let (cond, then, else_) = (arc(cond), arc(then), arc(else_));
+            quote!(::blambda::Expr::Ternary { cond: #cond, then: #then, else_: #else_ })
+        }
+    }
+}
+
+/// Rust code evaluating `expr`, with each variable read from its binding.
+fn eval_expr(expr: &Expr, bindings: &HashMap<String, Ident>) -> TokenStream {
+    let eval = |expr: &Expr| eval_expr(expr, bindings);
+    match expr {
+        Expr::Value(v) => quote!(#v),
+        Expr::Unknown => unreachable!("bound expressions are two-valued"),
+        Expr::Var(name) => {
+            let var = &bindings[name];
+            quote!(#var)
+        }
+        Expr::Unary { op, arg } => match op {
+            UnOp::Not => {
+                let arg = eval(arg);
+                quote!(!#arg)
+            }
+        },
+        Expr::Binary { op, arg1, arg2 } => {
+            let (v1, v2) = (eval(arg1), eval(arg2));
+            match op {
+                BinOp::Or => quote!((#v1 | #v2)),
+                BinOp::And => quote!((#v1 & #v2)),
+                BinOp::Xor => quote!((#v1 ^ #v2)),
+                BinOp::Nand => quote!(!(#v1 & #v2)),
+                BinOp::Nor => quote!(!(#v1 | #v2)),
+                BinOp::Implies => quote!((!#v1 | #v2)),
+                BinOp::Iff => quote!((#v1 == #v2)),
+            }
+        }
+        Expr::And(args) => {
+            let args = args.iter().map(eval);
+            quote!((true #(& #args)*))
+        }
+        Expr::Or(args) => {
+            let args = args.iter().map(eval);
+            quote!((false #(| #args)*))
+        }
+        Expr::Ternary { cond, then, else_ } => {
+            let (cond, then, else_) = (eval(cond), eval(then), eval(else_));
+            quote!((if #cond { #then } else { #else_ }))
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use blambda::{BinOp, Expr, UnOp};
+use blambda_macros::blambda;
+
+#[test]
+fn test_embedded_expr() {
+    assert_eq!(
+        blambda!(a & ~b),
+        Expr::Binary {
+            op: BinOp::And,
+            arg1: Arc::new(Expr::Var("a".to_string())),
+            arg2: Arc::new(Expr::Unary {
+                op: UnOp::Not,
+                arg: Arc::new(Expr::Var("b".to_string())),
+            }),
+        }
+    );
+
+    // Every ASCII and keyword spelling survives Rust's tokenizer
+    let sources = [
+        (blambda!(a | b & c ^ d), "a | b & c ^ d"),
+        (blambda!(a !& b !| c), "a !& b !| c"),
+        (blambda!(a->b<->~c), "a -> b <-> ~c"),
+        (blambda!(a ? (t) : F), "a ? t : f"),
+        (blambda!(not a and b or true xor false), "~a & b | t ^ f"),
+        (
+            blambda!(a nand b nor c implies d iff e),
+            "a !& b !| c -> d <-> e",
+        ),
+    ];
+    for (expr, source) in sources {
+        assert_eq!(expr, source.parse().unwrap());
+    }
+}
+
+#[test]
+fn test_bound_expr() {
+    let truth = |a: bool, b: bool, c: bool| blambda!(a -> b ? ~c : c <-> a, a = a, b = b, c = c);
+    let expr: Expr = "a -> b ? ~c : c <-> a".parse().unwrap();
+    for row in 0..8 {
+        let (a, b, c) = (row & 4 != 0, row & 2 != 0, row & 1 != 0);
+        let env = [("a", a), ("b", b), ("c", c)]
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        let program = blambda::Program {
+            defs: Vec::new(),
+            exprs: vec![expr.clone()],
+        };
+        assert_eq!(vec![truth(a, b, c)], program.eval_in(&env).unwrap());
+    }
+
+    // Bindings are evaluated once each, in order, even if unused
+    let mut calls = Vec::new();
+    let mut call = |name: &'static str, value: bool| {
+        calls.push(name);
+        value
+    };
+    assert!(blambda!(
+        y | (x & x),
+        x = call("x", false),
+        y = call("y", true)
+    ));
+    assert_eq!(calls, ["x", "y"]);
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::blambda::error::BlambdaError;
+use crate::blambda::eval::Env;
+use crate::blambda::syntax::Expr;
+
+/// Syntax of a file of variable assignments, one assignment per row.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AssignmentFormat {
+    /// A sequence of maps from variable names to values.
+    Yaml,
+    /// An array of objects from variable names to values.
+    Json,
+    /// A header row of variable names, then one row of values per assignment.
+    Csv,
+}
+
+impl AssignmentFormat {
+    /// The format implied by a file's extension, if it names one.
+    pub fn from_path(path: &str) -> Option<AssignmentFormat> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(AssignmentFormat::Yaml),
+            "json" => Some(AssignmentFormat::Json),
+            "csv" => Some(AssignmentFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// A truth value as written in an assignment file.
+#[derive(serde_derive::Deserialize)]
+#[serde(untagged)]
+enum Cell {
+    Bool(bool),
+    Int(u64),
+    Text(String),
+}
+
+/// Parses a truth value written as any spelling of `t` or `f`.
+pub fn parse_value(text: &str) -> Option<bool> {
+    match text.trim().parse::<Expr>() {
+        Ok(Expr::Value(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn cell_value(name: &str, cell: Cell) -> Result<bool, BlambdaError> {
+    match cell {
+        Cell::Bool(value) => Ok(value),
+        Cell::Int(n @ (0 | 1)) => Ok(n == 1),
+        Cell::Int(n) => Err(BlambdaError::new(format!(
+            "`{}` is assigned {}, which is not a truth value",
+            name, n
+        ))),
+        Cell::Text(text) => parse_value(&text).ok_or_else(|| {
+            BlambdaError::new(format!(
+                "`{}` is assigned `{}`, which is not a truth value",
+                name, text
+            ))
+        }),
+    }
+}
+
+fn row_env(row: HashMap<String, Option<Cell>>) -> Result<Env, BlambdaError> {
+    // empty cells leave their variable unassigned
+    row.into_iter()
+        .filter_map(|(name, cell)| cell.map(|cell| (name, cell)))
+        .map(|(name, cell)| cell_value(&name, cell).map(|value| (name, value)))
+        .collect()
+}
+
+/// Reads one environment per row of an assignment file. Values may be
+/// booleans, `0` or `1`, or any spelling of `t` or `f`; empty and null
+/// values leave the variable unassigned.
+pub fn read_assignments(
+    reader: impl Read,
+    format: AssignmentFormat,
+) -> Result<Vec<Env>, BlambdaError> {
+    let rows: Vec<HashMap<String, Option<Cell>>> = match format {
+        AssignmentFormat::Yaml => serde_yaml::from_reader(reader)?,
+        AssignmentFormat::Json => serde_json::from_reader(reader)?,
+        AssignmentFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader)
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+    };
+    rows.into_iter().map(row_env).collect()
+}
+
+/// Collects the assignments given by environment variables starting with
+/// `prefix`, each naming the variable after the prefix, e.g. `BL_x=t` with
+/// prefix `BL_`.
+pub fn env_assignments(
+    prefix: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Env, BlambdaError> {
+    vars.into_iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix(prefix)?.to_string(), value)))
+        .filter(|(name, _)| !name.is_empty())
+        .map(|(name, value)| cell_value(&name, Cell::Text(value)).map(|value| (name, value)))
+        .collect()
+}
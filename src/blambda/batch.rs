@@ -0,0 +1,231 @@
+use crate::blambda::error::EvalError;
+use crate::blambda::normalize::{free_vars, has_unknown};
+use crate::blambda::syntax::{BinOp, Expr, UnOp};
+
+/// Lanes of 64 assignments evaluated together by `truth_table_bits`.
+const TABLE_LANES: usize = 4;
+
+/// The most free variables `truth_table_bits` tabulates, a table of 512MiB.
+pub const MAX_BIT_TABLE_VARS: usize = 32;
+
+/// An instruction of the word-wise stack machine. Unlike `compile::Op` there
+/// are no jumps, since each bit of a word may take a different path.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum BatchOp {
+    Const(bool),
+    Load(usize),
+    Not,
+    Binary(BinOp),
+    /// Pops the given number of operands and pushes their conjunction.
+    And(usize),
+    Or(usize),
+    /// Pops a condition and both branches, and pushes each bit of the branch
+    /// its condition chooses.
+    Select,
+}
+
+/// An expression lowered for evaluating many assignments at once, with each
+/// variable's values packed into the bits of words.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BatchCode {
+    ops: Vec<BatchOp>,
+    /// Free variables by slot, in order of first appearance.
+    pub vars: Vec<String>,
+}
+
+fn apply_word_binop(op: BinOp, w1: u64, w2: u64) -> u64 {
+    match op {
+        BinOp::Or => w1 | w2,
+        BinOp::And => w1 & w2,
+        BinOp::Xor => w1 ^ w2,
+        BinOp::Nand => !(w1 & w2),
+        BinOp::Nor => !(w1 | w2),
+        BinOp::Implies => !w1 | w2,
+        BinOp::Iff => !(w1 ^ w2),
+    }
+}
+
+fn lower(expr: &Expr, vars: &[String], ops: &mut Vec<BatchOp>) {
+    match expr {
+        Expr::Value(v) => ops.push(BatchOp::Const(*v)),
+        Expr::Unknown => panic!("`u` has no two-valued bytecode"),
+        Expr::Var(name) => ops.push(BatchOp::Load(
+            vars.iter().position(|var| var == name).unwrap(),
+        )),
+        Expr::Unary { op, arg } => match op {
+            UnOp::Not => {
+                lower(arg, vars, ops);
+                ops.push(BatchOp::Not);
+            }
+        },
+        Expr::Binary { op, arg1, arg2 } => {
+            lower(arg1, vars, ops);
+            lower(arg2, vars, ops);
+            ops.push(BatchOp::Binary(*op));
+        }
+        Expr::And(args) => {
+            args.iter().for_each(|arg| lower(arg, vars, ops));
+            ops.push(BatchOp::And(args.len()));
+        }
+        Expr::Or(args) => {
+            args.iter().for_each(|arg| lower(arg, vars, ops));
+            ops.push(BatchOp::Or(args.len()));
+        }
+        Expr::Ternary { cond, then, else_ } => {
+            lower(cond, vars, ops);
+            lower(then, vars, ops);
+            lower(else_, vars, ops);
+            ops.push(BatchOp::Select);
+        }
+    }
+}
+
+/// Compiles an expression for batch evaluation, assigning each free variable
+/// a slot.
+///
+/// # Panics
+///
+/// If the expression contains `u`; see `normalize::has_unknown`.
+pub fn compile_batch(expr: &Expr) -> BatchCode {
+    let vars = free_vars(expr);
+    let mut ops = Vec::new();
+    lower(expr, &vars, &mut ops);
+    BatchCode { ops, vars }
+}
+
+impl BatchCode {
+    /// Evaluates `64 * N` assignments at once. `inputs[i]` holds the values
+    /// of the variable in slot `i`, with bit `j` of lane `k` belonging to
+    /// assignment `64 * k + j`; the result is laid out the same way.
+    ///
+    /// # Panics
+    ///
+    /// If `inputs` has fewer words than the code has variables.
+    pub fn eval<const N: usize>(&self, inputs: &[[u64; N]]) -> [u64; N] {
+        assert!(
+            inputs.len() >= self.vars.len(),
+            "expected {} inputs, got {}",
+            self.vars.len(),
+            inputs.len()
+        );
+        let mut stack: Vec<[u64; N]> = Vec::new();
+        for op in &self.ops {
+            match *op {
+                BatchOp::Const(v) => stack.push([if v { !0 } else { 0 }; N]),
+                BatchOp::Load(slot) => stack.push(inputs[slot]),
+                BatchOp::Not => stack.last_mut().unwrap().iter_mut().for_each(|w| *w = !*w),
+                BatchOp::Binary(op) => {
+                    let w2 = stack.pop().unwrap();
+                    let w1 = stack.last_mut().unwrap();
+                    (0..N).for_each(|lane| w1[lane] = apply_word_binop(op, w1[lane], w2[lane]));
+                }
+                BatchOp::And(len) | BatchOp::Or(len) => {
+                    let is_and = matches!(op, BatchOp::And(_));
+                    let mut acc = [if is_and { !0 } else { 0 }; N];
+                    for w in stack.drain(stack.len() - len..) {
+                        (0..N).for_each(|lane| match is_and {
+                            true => acc[lane] &= w[lane],
+                            false => acc[lane] |= w[lane],
+                        });
+                    }
+                    stack.push(acc);
+                }
+                BatchOp::Select => {
+                    let else_ = stack.pop().unwrap();
+                    let then = stack.pop().unwrap();
+                    let cond = stack.last_mut().unwrap();
+                    (0..N).for_each(|lane| {
+                        cond[lane] = (cond[lane] & then[lane]) | (!cond[lane] & else_[lane])
+                    });
+                }
+            }
+        }
+        stack.pop().unwrap()
+    }
+}
+
+/// A truth table packed one bit per row, numbered as in `TruthTable`: counting
+/// up in binary with the first variable most significant.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BitTable {
+    /// Free variables, in order of first appearance.
+    pub vars: Vec<String>,
+    /// The output of row `r` is bit `r % 64` of word `r / 64`. Bits past the
+    /// last row are zero.
+    pub words: Vec<u64>,
+}
+
+impl BitTable {
+    /// The number of rows, which fits as tables have at most
+    /// `MAX_BIT_TABLE_VARS` variables.
+    pub fn rows(&self) -> u64 {
+        1 << self.vars.len()
+    }
+
+    pub fn get(&self, row: u64) -> bool {
+        self.words[(row / 64) as usize] >> (row % 64) & 1 == 1
+    }
+
+    /// The number of rows in which the expression is true.
+    pub fn count_true(&self) -> u64 {
+        self.words.iter().map(|w| u64::from(w.count_ones())).sum()
+    }
+}
+
+/// Bit `position` of each of the row numbers `64 * word` to `64 * word + 63`.
+fn row_bits(position: usize, word: u64) -> u64 {
+    const LOW_BITS: [u64; 6] = [
+        0xAAAA_AAAA_AAAA_AAAA,
+        0xCCCC_CCCC_CCCC_CCCC,
+        0xF0F0_F0F0_F0F0_F0F0,
+        0xFF00_FF00_FF00_FF00,
+        0xFFFF_0000_FFFF_0000,
+        0xFFFF_FFFF_0000_0000,
+    ];
+    match position {
+        0..6 => LOW_BITS[position],
+        _ if word >> (position - 6) & 1 == 1 => !0,
+        _ => 0,
+    }
+}
+
+/// Tabulates an expression over every assignment to its free variables,
+/// `64 * TABLE_LANES` rows at a time. The table takes `2^n / 8` bytes for `n`
+/// variables, so there is none for more than `MAX_BIT_TABLE_VARS` variables,
+/// nor for an expression containing `u`.
+pub fn truth_table_bits(expr: &Expr) -> Result<BitTable, EvalError> {
+    if has_unknown(expr) {
+        return Err(EvalError::Unknown);
+    }
+    let code = compile_batch(expr);
+    let width = code.vars.len();
+    if width > MAX_BIT_TABLE_VARS {
+        return Err(EvalError::TooManyVars {
+            vars: width,
+            max: MAX_BIT_TABLE_VARS,
+        });
+    }
+    let rows = 1u64 << width;
+    let len = rows.div_ceil(64) as usize;
+
+    let mut words = Vec::with_capacity(len);
+    let mut inputs = vec![[0u64; TABLE_LANES]; width];
+    for block in (0..len).step_by(TABLE_LANES) {
+        for (slot, input) in inputs.iter_mut().enumerate() {
+            // the first variable is the most significant bit of the row number
+            let position = width - 1 - slot;
+            for (lane, word) in input.iter_mut().enumerate() {
+                *word = row_bits(position, (block + lane) as u64);
+            }
+        }
+        let output = code.eval(&inputs);
+        words.extend(output.iter().take(len - block));
+    }
+    if rows < 64 {
+        words[0] &= (1 << rows) - 1;
+    }
+    Ok(BitTable {
+        vars: code.vars,
+        words,
+    })
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use num_bigint::BigUint;
+
+use crate::blambda::dag::{Node, NodeId, ProgramDag};
+use crate::blambda::error::EvalError;
+use crate::blambda::syntax::{BinOp, Program, UnOp};
+
+/// Identifies a node of a `Bdd`. Diagrams are reduced and hash-consed, so two
+/// refs of the same `Bdd` are equal exactly when their functions are.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct BddRef(u32);
+
+impl BddRef {
+    pub const FALSE: BddRef = BddRef(0);
+    pub const TRUE: BddRef = BddRef(1);
+
+    pub fn is_terminal(self) -> bool {
+        self.0 < 2
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+struct BddNode {
+    level: u32,
+    low: BddRef,
+    high: BddRef,
+}
+
+/// A shared store of reduced ordered binary decision diagrams. Variables are
+/// ordered by when they were first used.
+#[derive(Debug, Clone)]
+pub struct Bdd {
+    nodes: Vec<BddNode>,
+    unique: HashMap<BddNode, BddRef>,
+    ite_cache: HashMap<(BddRef, BddRef, BddRef), BddRef>,
+    vars: Vec<String>,
+    levels: HashMap<String, u32>,
+}
+
+impl Default for Bdd {
+    fn default() -> Self {
+        let terminal = |value| BddNode {
+            level: u32::MAX,
+            low: BddRef(value),
+            high: BddRef(value),
+        };
+        Bdd {
+            nodes: vec![terminal(0), terminal(1)],
+            unique: HashMap::new(),
+            ite_cache: HashMap::new(),
+            vars: Vec::new(),
+            levels: HashMap::new(),
+        }
+    }
+}
+
+impl Bdd {
+    pub fn new() -> Self {
+        Bdd::default()
+    }
+
+    /// The position of a variable in the order, adding it last if it is new.
+    pub fn level(&mut self, name: &str) -> u32 {
+        if let Some(level) = self.levels.get(name) {
+            return *level;
+        }
+        let level = self.vars.len() as u32;
+        self.vars.push(name.to_string());
+        self.levels.insert(name.to_string(), level);
+        level
+    }
+
+    fn mk(&mut self, level: u32, low: BddRef, high: BddRef) -> BddRef {
+        if low == high {
+            return low;
+        }
+        let node = BddNode { level, low, high };
+        if let Some(id) = self.unique.get(&node) {
+            return *id;
+        }
+        let id = BddRef(self.nodes.len() as u32);
+        self.nodes.push(node);
+        self.unique.insert(node, id);
+        id
+    }
+
+    fn top(&self, f: BddRef) -> u32 {
+        self.nodes[f.0 as usize].level
+    }
+
+    /// The cofactors of `f` with the variable at `level` set to false and true.
+    fn cofactors(&self, f: BddRef, level: u32) -> (BddRef, BddRef) {
+        let node = self.nodes[f.0 as usize];
+        if node.level == level {
+            (node.low, node.high)
+        } else {
+            (f, f)
+        }
+    }
+
+    /// The function which is true exactly when the variable `name` is.
+    pub fn var(&mut self, name: &str) -> BddRef {
+        let level = self.level(name);
+        self.mk(level, BddRef::FALSE, BddRef::TRUE)
+    }
+
+    /// If-then-else, from which every other connective is built.
+    pub fn ite(&mut self, f: BddRef, g: BddRef, h: BddRef) -> BddRef {
+        if f == BddRef::TRUE || g == h {
+            return g;
+        }
+        if f == BddRef::FALSE {
+            return h;
+        }
+        if g == BddRef::TRUE && h == BddRef::FALSE {
+            return f;
+        }
+        if let Some(id) = self.ite_cache.get(&(f, g, h)) {
+            return *id;
+        }
+        let level = self.top(f).min(self.top(g)).min(self.top(h));
+        let (f0, f1) = self.cofactors(f, level);
+        let (g0, g1) = self.cofactors(g, level);
+        let (h0, h1) = self.cofactors(h, level);
+        let low = self.ite(f0, g0, h0);
+        let high = self.ite(f1, g1, h1);
+        let id = self.mk(level, low, high);
+        self.ite_cache.insert((f, g, h), id);
+        id
+    }
+
+    pub fn not(&mut self, f: BddRef) -> BddRef {
+        self.ite(f, BddRef::FALSE, BddRef::TRUE)
+    }
+
+    pub fn apply(&mut self, op: BinOp, f: BddRef, g: BddRef) -> BddRef {
+        match op {
+            BinOp::Or => self.ite(f, BddRef::TRUE, g),
+            BinOp::And => self.ite(f, g, BddRef::FALSE),
+            BinOp::Xor => {
+                let not_g = self.not(g);
+                self.ite(f, not_g, g)
+            }
+            BinOp::Nand => {
+                let not_g = self.not(g);
+                self.ite(f, not_g, BddRef::TRUE)
+            }
+            BinOp::Nor => {
+                let not_g = self.not(g);
+                self.ite(f, BddRef::FALSE, not_g)
+            }
+            BinOp::Implies => self.ite(f, g, BddRef::TRUE),
+            BinOp::Iff => {
+                let not_g = self.not(g);
+                self.ite(f, g, not_g)
+            }
+        }
+    }
+
+    /// Existentially quantifies away every variable whose level is marked in
+    /// `quantified`.
+    pub fn exists(&mut self, f: BddRef, quantified: &[bool]) -> BddRef {
+        let mut memo = HashMap::new();
+        self.exists_memo(f, quantified, &mut memo)
+    }
+
+    fn exists_memo(
+        &mut self,
+        f: BddRef,
+        quantified: &[bool],
+        memo: &mut HashMap<BddRef, BddRef>,
+    ) -> BddRef {
+        if f.is_terminal() {
+            return f;
+        }
+        if let Some(id) = memo.get(&f) {
+            return *id;
+        }
+        let BddNode { level, low, high } = self.nodes[f.0 as usize];
+        let low = self.exists_memo(low, quantified, memo);
+        let high = self.exists_memo(high, quantified, memo);
+        let id = if quantified.get(level as usize).copied().unwrap_or(false) {
+            self.apply(BinOp::Or, low, high)
+        } else {
+            self.mk(level, low, high)
+        };
+        memo.insert(f, id);
+        id
+    }
+
+    /// The number of assignments to the variables at `over` which satisfy
+    /// `f`, whose variables must all be among them.
+    ///
+    /// # Panics
+    ///
+    /// If `f` depends on a variable not in `over`.
+    pub fn count(&self, f: BddRef, over: &[u32]) -> BigUint {
+        let mut over = over.to_vec();
+        over.sort_unstable();
+        over.dedup();
+        let mut memo = HashMap::new();
+        let models = self.count_memo(f, &over, &mut memo);
+        models << self.position(f, &over)
+    }
+
+    /// How many of the variables in `over` come before the top of `f`.
+    fn position(&self, f: BddRef, over: &[u32]) -> usize {
+        if f.is_terminal() {
+            return over.len();
+        }
+        over.binary_search(&self.top(f))
+            .expect("counted function depends on a variable outside the count")
+    }
+
+    /// Models of `f` over the variables of `over` from its top variable on.
+    fn count_memo(&self, f: BddRef, over: &[u32], memo: &mut HashMap<BddRef, BigUint>) -> BigUint {
+        if f.is_terminal() {
+            return BigUint::from(f.0);
+        }
+        if let Some(models) = memo.get(&f) {
+            return models.clone();
+        }
+        let BddNode { low, high, .. } = self.nodes[f.0 as usize];
+        let position = self.position(f, over);
+        let mut branch = |child: BddRef| {
+            let skipped = self.position(child, over) - position - 1;
+            self.count_memo(child, over, memo) << skipped
+        };
+        let models = branch(low) + branch(high);
+        memo.insert(f, models.clone());
+        models
+    }
+}
+
+/// Builds a diagram for every node of a program's arena, in order. Nodes that
+/// depend on `u` have none, since `u` is not a function of the variables.
+fn build_nodes(bdd: &mut Bdd, dag: &ProgramDag) -> Vec<Option<BddRef>> {
+    let mut built: Vec<Option<BddRef>> = Vec::with_capacity(dag.arena.len());
+    for node in dag.arena.nodes() {
+        let get = |id: &NodeId| built[id.index()];
+        let f = match node {
+            Node::Value(true) => Some(BddRef::TRUE),
+            Node::Value(false) => Some(BddRef::FALSE),
+            Node::Unknown => None,
+            Node::Var(name) => Some(bdd.var(name)),
+            Node::Unary { op, arg } => match op {
+                UnOp::Not => get(arg).map(|f| bdd.not(f)),
+            },
+            Node::Binary { op, arg1, arg2 } => {
+                get(arg1).zip(get(arg2)).map(|(f, g)| bdd.apply(*op, f, g))
+            }
+            Node::And(args) => args.iter().try_fold(BddRef::TRUE, |acc, arg| {
+                get(arg).map(|f| bdd.apply(BinOp::And, acc, f))
+            }),
+            Node::Or(args) => args.iter().try_fold(BddRef::FALSE, |acc, arg| {
+                get(arg).map(|f| bdd.apply(BinOp::Or, acc, f))
+            }),
+            Node::Ternary { cond, then, else_ } => match (get(cond), get(then), get(else_)) {
+                (Some(c), Some(t), Some(e)) => Some(bdd.ite(c, t, e)),
+                _ => None,
+            },
+        };
+        built.push(f);
+    }
+    built
+}
+
+/// The variables of the expression at `id`, which need not all be in its
+/// diagram, e.g. `a | ~a` depends on no variables but has `a` free.
+fn node_vars(bdd: &mut Bdd, dag: &ProgramDag, id: NodeId) -> Vec<u32> {
+    let mut seen = vec![false; dag.arena.len()];
+    let mut stack = vec![id];
+    let mut levels = Vec::new();
+    while let Some(id) = stack.pop() {
+        if !std::mem::replace(&mut seen[id.index()], true) {
+            let node = dag.arena.node(id);
+            if let Node::Var(name) = node {
+                levels.push(bdd.level(name));
+            }
+            stack.extend(node.children());
+        }
+    }
+    levels
+}
+
+/// Counts the satisfying assignments of each expression of a program over
+/// its free variables, with definitions inlined. With a `projection`, counts
+/// instead the assignments to just those variables which extend to a
+/// satisfying assignment of the rest.
+pub fn count_models(
+    program: &Program,
+    projection: Option<&[String]>,
+) -> Result<Vec<BigUint>, EvalError> {
+    let dag = ProgramDag::inlined(program);
+    let mut bdd = Bdd::new();
+    let built = build_nodes(&mut bdd, &dag);
+    let projection: Option<Vec<u32>> =
+        projection.map(|names| names.iter().map(|name| bdd.level(name)).collect());
+    dag.exprs
+        .iter()
+        .map(|id| {
+            let f = built[id.index()].ok_or(EvalError::Unknown)?;
+            match &projection {
+                Some(over) => {
+                    let mut quantified = vec![true; bdd.vars.len()];
+                    for level in over {
+                        quantified[*level as usize] = false;
+                    }
+                    let f = bdd.exists(f, &quantified);
+                    Ok(bdd.count(f, over))
+                }
+                None => {
+                    let over = node_vars(&mut bdd, &dag, *id);
+                    Ok(bdd.count(f, &over))
+                }
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,238 @@
+use crate::blambda::eval::{apply_binop, Env};
+use crate::blambda::normalize::free_vars;
+use crate::blambda::syntax::{BinOp, Expr, UnOp};
+
+/// An instruction of the stack machine. Jump targets are indices into
+/// `Bytecode::ops`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Op {
+    /// Pushes a constant.
+    Push(bool),
+    /// Pushes the input assigned to the variable in the given slot.
+    Load(usize),
+    /// Negates the top of the stack.
+    Not,
+    /// Pops two operands and pushes the result of the operator.
+    Binary(BinOp),
+    /// Pops a condition, jumping if it is false.
+    JumpIfFalse(usize),
+    /// Jumps if the top of the stack is false, keeping it as the result of a
+    /// short-circuited `&`; otherwise pops it.
+    JumpIfFalseOrPop(usize),
+    /// Jumps if the top of the stack is true, keeping it as the result of a
+    /// short-circuited `|`; otherwise pops it.
+    JumpIfTrueOrPop(usize),
+    Jump(usize),
+}
+
+/// An expression lowered to a flat instruction sequence, which evaluates it
+/// against an assignment to its free variables without walking the tree.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bytecode {
+    pub ops: Vec<Op>,
+    /// Free variables by slot, in order of first appearance.
+    pub vars: Vec<String>,
+    /// Deepest the stack gets while running, so it can be allocated up front.
+    pub max_stack: usize,
+}
+
+struct Compiler {
+    ops: Vec<Op>,
+    vars: Vec<String>,
+    height: usize,
+    max_stack: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        match op {
+            Op::Push(_) | Op::Load(_) => self.height += 1,
+            Op::Binary(_) | Op::JumpIfFalse(_) => self.height -= 1,
+            // the jumping path keeps the operand, but falls through with it popped
+            Op::JumpIfFalseOrPop(_) | Op::JumpIfTrueOrPop(_) => self.height -= 1,
+            Op::Not | Op::Jump(_) => (),
+        }
+        self.max_stack = self.max_stack.max(self.height);
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// Points the jump emitted at `index` at the next instruction.
+    fn patch(&mut self, index: usize) {
+        let target = self.ops.len();
+        match &mut self.ops[index] {
+            Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to)
+            | Op::Jump(to) => *to = target,
+            op => unreachable!("patching non-jump {:?}", op),
+        }
+    }
+
+    /// Short-circuits a chain of `&` (or `|`) operands, jumping to its end as
+    /// soon as one of them decides the result.
+    fn compile_chain<'a>(&mut self, is_and: bool, args: impl IntoIterator<Item = &'a Expr>) {
+        let mut args = args.into_iter().peekable();
+        if args.peek().is_none() {
+            self.emit(Op::Push(is_and));
+            return;
+        }
+        let mut jumps = Vec::new();
+        while let Some(arg) = args.next() {
+            self.compile(arg);
+            if args.peek().is_some() {
+                jumps.push(self.emit(match is_and {
+                    true => Op::JumpIfFalseOrPop(0),
+                    false => Op::JumpIfTrueOrPop(0),
+                }));
+            }
+        }
+        jumps.into_iter().for_each(|jump| self.patch(jump));
+    }
+
+    fn compile(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Value(v) => {
+                self.emit(Op::Push(*v));
+            }
+            Expr::Unknown => panic!("`u` has no two-valued bytecode"),
+            Expr::Var(name) => {
+                let slot = self.vars.iter().position(|var| var == name).unwrap();
+                self.emit(Op::Load(slot));
+            }
+            Expr::Unary { op, arg } => match op {
+                UnOp::Not => {
+                    self.compile(arg);
+                    self.emit(Op::Not);
+                }
+            },
+            Expr::Binary { op, arg1, arg2 } => match op {
+                BinOp::And => self.compile_chain(true, [arg1.as_ref(), arg2.as_ref()]),
+                BinOp::Or => self.compile_chain(false, [arg1.as_ref(), arg2.as_ref()]),
+                _ => {
+                    self.compile(arg1);
+                    self.compile(arg2);
+                    self.emit(Op::Binary(*op));
+                }
+            },
+            Expr::And(args) => self.compile_chain(true, args),
+            Expr::Or(args) => self.compile_chain(false, args),
+            Expr::Ternary { cond, then, else_ } => {
+                self.compile(cond);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.compile(then);
+                let to_end = self.emit(Op::Jump(0));
+                // only one branch runs, so the else branch starts from the same height
+                self.height -= 1;
+                self.patch(to_else);
+                self.compile(else_);
+                self.patch(to_end);
+            }
+        }
+    }
+}
+
+/// Compiles an expression to bytecode, assigning each free variable a slot.
+///
+/// # Panics
+///
+/// If the expression contains `u`; see `normalize::has_unknown`.
+pub fn compile(expr: &Expr) -> Bytecode {
+    let mut compiler = Compiler {
+        ops: Vec::new(),
+        vars: free_vars(expr),
+        height: 0,
+        max_stack: 0,
+    };
+    compiler.compile(expr);
+    Bytecode {
+        ops: compiler.ops,
+        vars: compiler.vars,
+        max_stack: compiler.max_stack,
+    }
+}
+
+/// A stack machine for running bytecode, whose stack is reused between runs.
+#[derive(Debug, Default)]
+pub struct Vm {
+    stack: Vec<bool>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm { stack: Vec::new() }
+    }
+
+    /// Runs `code` with `inputs[i]` assigned to the variable in slot `i`.
+    ///
+    /// # Panics
+    ///
+    /// If `inputs` has fewer values than `code` has variables.
+    pub fn run(&mut self, code: &Bytecode, inputs: &[bool]) -> bool {
+        assert!(
+            inputs.len() >= code.vars.len(),
+            "expected {} inputs, got {}",
+            code.vars.len(),
+            inputs.len()
+        );
+        let stack = &mut self.stack;
+        stack.clear();
+        stack.reserve(code.max_stack);
+        let mut pc = 0;
+        while let Some(op) = code.ops.get(pc) {
+            pc += 1;
+            match *op {
+                Op::Push(v) => stack.push(v),
+                Op::Load(slot) => stack.push(inputs[slot]),
+                Op::Not => {
+                    let top = stack.last_mut().unwrap();
+                    *top = !*top;
+                }
+                Op::Binary(op) => {
+                    let v2 = stack.pop().unwrap();
+                    let v1 = stack.pop().unwrap();
+                    stack.push(apply_binop(op, v1, v2));
+                }
+                Op::JumpIfFalse(to) => {
+                    if !stack.pop().unwrap() {
+                        pc = to;
+                    }
+                }
+                Op::JumpIfFalseOrPop(to) => {
+                    if *stack.last().unwrap() {
+                        stack.pop();
+                    } else {
+                        pc = to;
+                    }
+                }
+                Op::JumpIfTrueOrPop(to) => {
+                    if *stack.last().unwrap() {
+                        pc = to;
+                    } else {
+                        stack.pop();
+                    }
+                }
+                Op::Jump(to) => pc = to,
+            }
+        }
+        stack.pop().unwrap()
+    }
+}
+
+impl Bytecode {
+    /// Runs the bytecode once on a fresh machine.
+    pub fn eval(&self, inputs: &[bool]) -> bool {
+        Vm::new().run(self, inputs)
+    }
+
+    /// Runs the bytecode with its variables looked up by name, failing if
+    /// any is unbound.
+    pub fn eval_in(&self, env: &Env) -> Option<bool> {
+        let inputs = self
+            .vars
+            .iter()
+            .map(|var| env.get(var).copied())
+            .collect::<Option<Vec<bool>>>()?;
+        Some(self.eval(&inputs))
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blambda::syntax::{BinOp, Definition, Expr, Program, UnOp};
+
+/// Identifies a node of an `ExprArena`. Nodes are hash-consed, so within one
+/// arena two ids are equal exactly when their expressions are structurally
+/// equal.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// An expression node whose operands are other nodes of the same arena.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Node {
+    Value(bool),
+    Unknown,
+    Var(String),
+    Unary {
+        op: UnOp,
+        arg: NodeId,
+    },
+    Binary {
+        op: BinOp,
+        arg1: NodeId,
+        arg2: NodeId,
+    },
+    And(Vec<NodeId>),
+    Or(Vec<NodeId>),
+    Ternary {
+        cond: NodeId,
+        then: NodeId,
+        else_: NodeId,
+    },
+}
+
+impl Node {
+    /// The operands of the node, left to right.
+    pub fn children(&self) -> Vec<NodeId> {
+        match self {
+            Node::Value(_) | Node::Unknown | Node::Var(_) => Vec::new(),
+            Node::Unary { arg, .. } => vec![*arg],
+            Node::Binary { arg1, arg2, .. } => vec![*arg1, *arg2],
+            Node::And(args) | Node::Or(args) => args.clone(),
+            Node::Ternary { cond, then, else_ } => vec![*cond, *then, *else_],
+        }
+    }
+}
+
+/// Stores expressions as a DAG in which every distinct sub-expression is a
+/// single node, however many times it occurs. Nodes are only ever added, and
+/// each node's operands are added before it.
+#[derive(Debug, Default, Clone)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, NodeId>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        ExprArena::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+
+    /// Every node, in the order added, so the node with id `i` is at index `i`.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the id of an existing node equal to `node`, or adds it.
+    pub fn intern(&mut self, node: Node) -> NodeId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    /// Adds an expression, sharing every sub-expression already in the arena.
+    pub fn add_expr(&mut self, expr: &Expr) -> NodeId {
+        self.add_expr_with(expr, &HashMap::new())
+    }
+
+    /// Adds an expression with each variable bound in `bindings` replaced by
+    /// its node, which is how definitions are inlined without copying them.
+    pub fn add_expr_with(&mut self, expr: &Expr, bindings: &HashMap<String, NodeId>) -> NodeId {
+        // sub-expressions shared through `Arc`s are only visited once, so
+        // trees built by substitution are not expanded
+        let mut visited: HashMap<*const Expr, NodeId> = HashMap::new();
+        self.add_shared(expr, bindings, &mut visited)
+    }
+
+    fn add_shared(
+        &mut self,
+        expr: &Expr,
+        bindings: &HashMap<String, NodeId>,
+        visited: &mut HashMap<*const Expr, NodeId>,
+    ) -> NodeId {
+        if let Some(id) = visited.get(&(expr as *const Expr)) {
+            return *id;
+        }
+        let mut add = |arena: &mut Self, child: &Expr| arena.add_shared(child, bindings, visited);
+        let id = match expr {
+            Expr::Value(v) => self.intern(Node::Value(*v)),
+            Expr::Unknown => self.intern(Node::Unknown),
+            Expr::Var(name) => match bindings.get(name) {
+                Some(id) => *id,
+                None => self.intern(Node::Var(name.clone())),
+            },
+            Expr::Unary { op, arg } => {
+                let arg = add(self, arg);
+                self.intern(Node::Unary { op: *op, arg })
+            }
+            Expr::Binary { op, arg1, arg2 } => {
+                let arg1 = add(self, arg1);
+                let arg2 = add(self, arg2);
+                self.intern(Node::Binary {
+                    op: *op,
+                    arg1,
+                    arg2,
+                })
+            }
+            Expr::And(args) => {
+                let args = args.iter().map(|arg| add(self, arg)).collect();
+                self.intern(Node::And(args))
+            }
+            Expr::Or(args) => {
+                let args = args.iter().map(|arg| add(self, arg)).collect();
+                self.intern(Node::Or(args))
+            }
+            Expr::Ternary { cond, then, else_ } => {
+                let cond = add(self, cond);
+                let then = add(self, then);
+                let else_ = add(self, else_);
+                self.intern(Node::Ternary { cond, then, else_ })
+            }
+        };
+        visited.insert(expr as *const Expr, id);
+        id
+    }
+
+    /// Rebuilds the expression of a node. Shared nodes become shared `Arc`s,
+    /// so the result is no larger than the DAG.
+    pub fn to_expr(&self, id: NodeId) -> Expr {
+        let mut built: HashMap<NodeId, Arc<Expr>> = HashMap::new();
+        Arc::unwrap_or_clone(self.build(id, &mut built))
+    }
+
+    fn build(&self, id: NodeId, built: &mut HashMap<NodeId, Arc<Expr>>) -> Arc<Expr> {
+        if let Some(expr) = built.get(&id) {
+            return expr.clone();
+        }
+        let expr = Arc::new(match self.node(id) {
+            Node::Value(v) => Expr::Value(*v),
+            Node::Unknown => Expr::Unknown,
+            Node::Var(name) => Expr::Var(name.clone()),
+            Node::Unary { op, arg } => Expr::Unary {
+                op: *op,
+                arg: self.build(*arg, built),
+            },
+            Node::Binary { op, arg1, arg2 } => Expr::Binary {
+                op: *op,
+                arg1: self.build(*arg1, built),
+                arg2: self.build(*arg2, built),
+            },
+            Node::And(args) => Expr::And(
+                args.iter()
+                    .map(|arg| self.build(*arg, built).as_ref().clone())
+                    .collect(),
+            ),
+            Node::Or(args) => Expr::Or(
+                args.iter()
+                    .map(|arg| self.build(*arg, built).as_ref().clone())
+                    .collect(),
+            ),
+            Node::Ternary { cond, then, else_ } => Expr::Ternary {
+                cond: self.build(*cond, built),
+                then: self.build(*then, built),
+                else_: self.build(*else_, built),
+            },
+        });
+        built.insert(id, expr.clone());
+        expr
+    }
+
+    /// The number of distinct nodes reachable from `id`, including itself.
+    pub fn reachable(&self, id: NodeId) -> usize {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![id];
+        let mut count = 0;
+        while let Some(id) = stack.pop() {
+            if !std::mem::replace(&mut seen[id.index()], true) {
+                count += 1;
+                stack.extend(self.node(id).children());
+            }
+        }
+        count
+    }
+}
+
+/// A program whose definitions and expressions share one `ExprArena`.
+#[derive(Debug, Default, Clone)]
+pub struct ProgramDag {
+    pub arena: ExprArena,
+    pub defs: Vec<(String, NodeId)>,
+    pub exprs: Vec<NodeId>,
+}
+
+impl ProgramDag {
+    /// Adds a program to a new arena, leaving variables which refer to
+    /// definitions as they are.
+    pub fn from_program(program: &Program) -> Self {
+        let mut arena = ExprArena::new();
+        let defs = program
+            .defs
+            .iter()
+            .map(|def| (def.name.clone(), arena.add_expr(&def.body)))
+            .collect();
+        let exprs = program
+            .exprs
+            .iter()
+            .map(|expr| arena.add_expr(expr))
+            .collect();
+        ProgramDag { arena, defs, exprs }
+    }
+
+    /// Adds a program to a new arena with its definitions inlined, as
+    /// `inline_definitions` does, so each definition's body is a single node
+    /// shared by all its uses.
+    pub fn inlined(program: &Program) -> Self {
+        let mut arena = ExprArena::new();
+        let mut bindings: HashMap<String, NodeId> = HashMap::new();
+        let mut defs = Vec::new();
+        for def in &program.defs {
+            let id = arena.add_expr_with(&def.body, &bindings);
+            bindings.insert(def.name.clone(), id);
+            defs.push((def.name.clone(), id));
+        }
+        let exprs = program
+            .exprs
+            .iter()
+            .map(|expr| arena.add_expr_with(expr, &bindings))
+            .collect();
+        ProgramDag { arena, defs, exprs }
+    }
+
+    pub fn to_program(&self) -> Program {
+        Program {
+            defs: self
+                .defs
+                .iter()
+                .map(|(name, id)| Definition {
+                    name: name.clone(),
+                    body: self.arena.to_expr(*id),
+                })
+                .collect(),
+            exprs: self
+                .exprs
+                .iter()
+                .map(|id| self.arena.to_expr(*id))
+                .collect(),
+        }
+    }
+}
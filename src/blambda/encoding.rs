@@ -0,0 +1,83 @@
+use base64::Engine;
+
+use crate::blambda::eval::{pack_bits, BitOrder};
+
+/// Shape in which the truth values of a program are written out by `eval`.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Encoding {
+    /// The packed integer in decimal.
+    #[default]
+    Decimal,
+    /// The packed integer in lowercase hexadecimal.
+    Hex,
+    /// The packed integer in octal.
+    Octal,
+    /// The packed integer in binary, one digit per expression.
+    Binary,
+    /// Base64 of the packed bytes.
+    Base64,
+    /// The packed bytes themselves.
+    Raw,
+    /// One `t` or `f` per expression, in program order.
+    Bits,
+}
+
+/// Packs `bits` into bytes, big-endian for `BitOrder::Msb` and little-endian
+/// for `BitOrder::Lsb`, so the first expression always lands in the first
+/// byte's most (resp. least) significant bit when the count is a multiple of 8.
+/// The result is padded to one byte per eight expressions.
+pub fn pack_bytes(bits: &[bool], order: BitOrder) -> Vec<u8> {
+    let value = pack_bits(bits, order);
+    let width = bits.len().div_ceil(8).max(1);
+    match order {
+        BitOrder::Msb => {
+            let bytes = value.to_bytes_be();
+            let mut padded = vec![0u8; width.saturating_sub(bytes.len())];
+            padded.extend(bytes);
+            padded
+        }
+        BitOrder::Lsb => {
+            let mut bytes = value.to_bytes_le();
+            bytes.resize(width.max(bytes.len()), 0);
+            bytes
+        }
+    }
+}
+
+/// Unpacks bytes into eight truth values each, the inverse of `pack_bytes`.
+pub fn unpack_bytes(bytes: &[u8], order: BitOrder) -> Vec<bool> {
+    match order {
+        BitOrder::Msb => bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0))
+            .collect(),
+        BitOrder::Lsb => bytes
+            .iter()
+            .flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0))
+            .collect(),
+    }
+}
+
+/// Encodes the truth values of a program. Every encoding except `Raw`
+/// produces printable text without a trailing newline.
+pub fn encode_bits(bits: &[bool], order: BitOrder, encoding: Encoding) -> Vec<u8> {
+    let value = pack_bits(bits, order);
+    match encoding {
+        Encoding::Decimal => value.to_str_radix(10).into_bytes(),
+        Encoding::Hex => value.to_str_radix(16).into_bytes(),
+        Encoding::Octal => value.to_str_radix(8).into_bytes(),
+        Encoding::Binary => {
+            format!("{:0>width$}", value.to_str_radix(2), width = bits.len()).into_bytes()
+        }
+        Encoding::Base64 => base64::engine::general_purpose::STANDARD
+            .encode(pack_bytes(bits, order))
+            .into_bytes(),
+        Encoding::Raw => pack_bytes(bits, order),
+        Encoding::Bits => bits
+            .iter()
+            .map(|bit| if *bit { "t" } else { "f" })
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .into_bytes(),
+    }
+}
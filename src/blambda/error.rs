@@ -1,26 +1,184 @@
+use std::ops::Range;
+use std::time::Duration;
+
 use super::parse::Rule;
+use super::serde_ast::serialize_expr;
+use super::syntax::Expr;
 
 #[derive(Debug)]
 pub struct BlambdaError {
     message: String,
+    location: Option<ErrorLocation>,
+}
+
+/// Where in the source a parse error was found, and the error without the
+/// rendered source snippet.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ErrorLocation {
+    /// Byte range of the offending input; empty for errors at a single position.
+    pub span: Range<usize>,
+    pub reason: String,
 }
 
-impl Into<BlambdaError> for std::io::Error {
-    fn into(self) -> BlambdaError {
+impl BlambdaError {
+    pub fn new(message: impl Into<String>) -> Self {
         BlambdaError {
-            message: format!("{}", self),
+            message: message.into(),
+            location: None,
         }
     }
+
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        self.location.as_ref()
+    }
+}
+
+/// A resource limit on evaluation, with its configured bound.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Limit {
+    Steps(u64),
+    Depth(usize),
+    Timeout(Duration),
+}
+
+/// Why a program could not be evaluated.
+#[derive(Debug, PartialEq, Clone)]
+pub enum EvalError {
+    /// A variable is neither defined nor assigned a value.
+    Unbound(String),
+    /// The program uses `u`, which only three-valued evaluation accepts.
+    Unknown,
+    /// A truth table over `vars` free variables was asked for, but at most
+    /// `max` are supported.
+    TooManyVars { vars: usize, max: usize },
+    /// Evaluation hit one of its resource limits while evaluating `at`, a
+    /// sub-expression of the `expr`th expression of the program, inside the
+    /// body of the definition `def` if there is one.
+    ResourceExhausted {
+        limit: Limit,
+        expr: usize,
+        def: Option<String>,
+        at: Expr,
+    },
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Unbound(name) => write!(f, "`{}` is not defined", name),
+            EvalError::Unknown => write!(f, "`u` can only be evaluated in three-valued logic"),
+            EvalError::TooManyVars { vars, max } => write!(
+                f,
+                "cannot tabulate {} free variables, at most {} are supported",
+                vars, max
+            ),
+            EvalError::ResourceExhausted {
+                limit,
+                expr,
+                def,
+                at,
+            } => {
+                match limit {
+                    Limit::Steps(max) => write!(f, "evaluation exceeded {} steps", max)?,
+                    Limit::Depth(max) => write!(f, "evaluation exceeded a depth of {}", max)?,
+                    Limit::Timeout(timeout) => {
+                        write!(f, "evaluation exceeded {}ms", timeout.as_millis())?
+                    }
+                }
+                write!(f, " in expression {}", expr)?;
+                if let Some(def) = def {
+                    write!(f, ", in the definition of `{}`", def)?;
+                }
+                // keep the report readable for large generated terms
+                let at = serialize_expr(at).map_err(|_| std::fmt::Error)?;
+                match at.char_indices().nth(60) {
+                    Some((end, _)) => write!(f, ", at `{}...`", &at[..end]),
+                    None => write!(f, ", at `{}`", at),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<EvalError> for BlambdaError {
+    fn from(error: EvalError) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+impl From<std::io::Error> for BlambdaError {
+    fn from(error: std::io::Error) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
 }
 
-impl Into<BlambdaError> for pest::error::Error<Rule> {
-    fn into(self) -> BlambdaError {
+impl From<pest::error::Error<Rule>> for BlambdaError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        let span = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos..pos,
+            pest::error::InputLocation::Span((start, end)) => start..end,
+        };
         BlambdaError {
-            message: format!("{}", self),
+            message: format!("{}", error),
+            location: Some(ErrorLocation {
+                span,
+                reason: error.variant.message().into_owned(),
+            }),
         }
     }
 }
 
+#[cfg(feature = "cli")]
+impl From<serde_yaml::Error> for BlambdaError {
+    fn from(error: serde_yaml::Error) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<serde_json::Error> for BlambdaError {
+    fn from(error: serde_json::Error) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<csv::Error> for BlambdaError {
+    fn from(error: csv::Error) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<ciborium::ser::Error<std::io::Error>> for BlambdaError {
+    fn from(error: ciborium::ser::Error<std::io::Error>) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<ciborium::de::Error<std::io::Error>> for BlambdaError {
+    fn from(error: ciborium::de::Error<std::io::Error>) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<rustyline::error::ReadlineError> for BlambdaError {
+    fn from(error: rustyline::error::ReadlineError) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<lsp_server::ProtocolError> for BlambdaError {
+    fn from(error: lsp_server::ProtocolError) -> Self {
+        BlambdaError::new(format!("{}", error))
+    }
+}
+
 impl std::fmt::Display for BlambdaError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "BlambdaError: {}", self.message)
@@ -1,66 +1,510 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Not;
+use std::time::{Duration, Instant};
+
+use crate::blambda::compile::{compile, Vm};
+use crate::blambda::dag::{Node, NodeId, ProgramDag};
+use crate::blambda::error::{EvalError, Limit};
+use crate::blambda::normalize::has_unknown;
 use crate::blambda::syntax::*;
 use num_bigint::BigUint;
 
+/// Truth values assigned to free variables.
+pub type Env = HashMap<String, bool>;
+
+pub fn apply_binop(op: BinOp, v1: bool, v2: bool) -> bool {
+    match op {
+        BinOp::Or => v1 || v2,
+        BinOp::And => v1 && v2,
+        BinOp::Xor => v1 ^ v2,
+        BinOp::Nand => !(v1 && v2),
+        BinOp::Nor => !(v1 || v2),
+        BinOp::Implies => !v1 || v2,
+        BinOp::Iff => v1 == v2,
+    }
+}
+
 pub fn evaluate_expr(expr: Expr) -> Option<bool> {
+    evaluate_expr_in(&expr, &Env::new())
+}
+
+/// Evaluates an expression, looking up its variables in `env`. Evaluation
+/// fails if a variable is unbound.
+pub fn evaluate_expr_in(expr: &Expr, env: &Env) -> Option<bool> {
     match expr {
-        Expr::Value(v) => Some(v),
+        Expr::Value(v) => Some(*v),
+        Expr::Unknown => None,
+        Expr::Var(name) => env.get(name).copied(),
         Expr::Unary { op, arg } => match op {
-            UnOp::Not => evaluate_expr((*arg).clone()).map(|v| !v),
+            UnOp::Not => evaluate_expr_in(arg, env).map(|v| !v),
         },
-        Expr::Binary {
-            op: BinOp::Condition,
-            arg1: condition,
-            arg2: branches,
-        } => {
-            let condition = evaluate_expr((*condition).clone());
-            match (condition, (*branches).clone()) {
-                (
-                    Some(condition),
-                    Expr::Binary {
-                        op: BinOp::Branch,
-                        arg1: branch1,
-                        arg2: branch2,
-                    },
-                ) => {
-                    if condition {
-                        evaluate_expr((*branch1).clone())
-                    } else {
-                        evaluate_expr((*branch2).clone())
-                    }
-                }
+        Expr::Binary { op, arg1, arg2 } => {
+            let v1 = evaluate_expr_in(arg1, env);
+            let v2 = evaluate_expr_in(arg2, env);
+            match (v1, v2) {
+                (Some(v1), Some(v2)) => Some(apply_binop(*op, v1, v2)),
                 _ => None,
             }
         }
-        Expr::Binary { op, arg1, arg2 } => match op {
-            BinOp::Or => {
-                let v1 = evaluate_expr((*arg1).clone());
-                let v2 = evaluate_expr((*arg2).clone());
-                match (v1, v2) {
-                    (Some(v1), Some(v2)) => Some(v1 || v2),
-                    _ => None,
-                }
+        Expr::And(args) => args.iter().try_fold(true, |acc, arg| {
+            evaluate_expr_in(arg, env).map(|v| acc && v)
+        }),
+        Expr::Or(args) => args.iter().try_fold(false, |acc, arg| {
+            evaluate_expr_in(arg, env).map(|v| acc || v)
+        }),
+        Expr::Ternary { cond, then, else_ } => match evaluate_expr_in(cond, env) {
+            Some(true) => evaluate_expr_in(then, env),
+            Some(false) => evaluate_expr_in(else_, env),
+            None => None,
+        },
+    }
+}
+
+/// The value of an expression under every assignment to its free variables.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TruthTable {
+    /// Free variables, in order of first appearance.
+    pub vars: Vec<String>,
+    /// One row per assignment, counting up in binary with the first variable
+    /// most significant.
+    pub rows: Vec<(Vec<bool>, bool)>,
+}
+
+/// The most free variables `truth_table` tabulates. Each row holds its own
+/// inputs, so a table of `n` variables takes around `2^n * (n + 32)` bytes.
+pub const MAX_TRUTH_TABLE_VARS: usize = 20;
+
+/// Tabulates an expression over its free variables, running it compiled to
+/// bytecode since it is evaluated once per row. There is no table for an
+/// expression containing `u`, or with more than `MAX_TRUTH_TABLE_VARS` free
+/// variables.
+pub fn truth_table(expr: &Expr) -> Result<TruthTable, EvalError> {
+    if has_unknown(expr) {
+        return Err(EvalError::Unknown);
+    }
+    let code = compile(expr);
+    let width = code.vars.len();
+    if width > MAX_TRUTH_TABLE_VARS {
+        return Err(EvalError::TooManyVars {
+            vars: width,
+            max: MAX_TRUTH_TABLE_VARS,
+        });
+    }
+    let mut vm = Vm::new();
+    let rows = (0..1u64 << width)
+        .map(|row| {
+            let inputs: Vec<bool> = (0..width)
+                .map(|i| row & (1 << (width - 1 - i)) != 0)
+                .collect();
+            let output = vm.run(&code, &inputs);
+            (inputs, output)
+        })
+        .collect();
+    Ok(TruthTable {
+        vars: code.vars,
+        rows,
+    })
+}
+
+/// Which end of the packed integer the first expression of a program occupies.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum BitOrder {
+    /// The first expression is the most significant bit.
+    #[default]
+    Msb,
+    /// The first expression is the least significant bit.
+    Lsb,
+}
+
+/// Evaluates each expression of a program, in program order, after
+/// substituting the program's definitions.
+pub fn evaluate_bits(program: Program) -> Option<Vec<bool>> {
+    let dag = ProgramDag::inlined(&program);
+    MemoEvaluator::new(&dag).eval_all(&Env::new()).ok()
+}
+
+impl Program {
+    /// Evaluates each expression of the program, in program order.
+    ///
+    /// ```
+    /// let program = blambda::parse_str("x = t & f  x | ~x  x").unwrap();
+    /// assert_eq!(program.eval(), Ok(vec![true, false]));
+    /// ```
+    pub fn eval(&self) -> Result<Vec<bool>, EvalError> {
+        self.eval_in(&Env::new())
+    }
+
+    /// Evaluates each expression of the program, in program order, looking up
+    /// variables which no definition binds in `env`.
+    ///
+    /// ```
+    /// use blambda::Env;
+    ///
+    /// let program = blambda::parse_str("a -> b").unwrap();
+    /// let env: Env = [("a".to_string(), true), ("b".to_string(), false)].into();
+    /// assert_eq!(program.eval_in(&env), Ok(vec![false]));
+    /// assert!(program.eval().is_err());
+    /// ```
+    pub fn eval_in(&self, env: &Env) -> Result<Vec<bool>, EvalError> {
+        MemoEvaluator::new(&ProgramDag::inlined(self)).eval_all(env)
+    }
+}
+
+/// How often a `MemoEvaluator` found the value of a node already computed.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct CacheStats {
+    /// Uses of a node whose value was cached.
+    pub hits: u64,
+    /// Uses of a node whose value had to be computed.
+    pub misses: u64,
+}
+
+/// Bounds on the work `evaluate_bits_with` may do, so that untrusted programs
+/// can be evaluated safely. Each is unbounded when `None`.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Limits {
+    /// Number of sub-expressions evaluated, across the whole program,
+    /// counting each use of an already computed one as a single step.
+    pub max_steps: Option<u64>,
+    /// Nesting depth of the sub-expression being evaluated, counting through
+    /// the bodies of the definitions it uses.
+    pub max_depth: Option<usize>,
+    /// Wall-clock time for evaluating the whole program.
+    pub timeout: Option<Duration>,
+}
+
+/// How often, in steps, the evaluator checks the clock against its timeout.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Evaluates the expressions of a `ProgramDag`, computing each distinct
+/// sub-expression at most once per assignment, under resource limits.
+///
+/// Variables naming a definition of the DAG are evaluated as its body, with
+/// each definition seeing only those before it, so the DAG may be built
+/// either with `ProgramDag::from_program` or with `ProgramDag::inlined`. The
+/// former keeps limit reports in terms of the source.
+pub struct MemoEvaluator<'a> {
+    dag: &'a ProgramDag,
+    limits: Limits,
+    /// The indices of the definitions of each name, in program order.
+    def_indices: HashMap<&'a str, Vec<usize>>,
+    /// Values by node and the number of definitions in scope, since a
+    /// variable may name a definition in one scope and be free in another.
+    values: HashMap<(NodeId, usize), bool>,
+    steps: u64,
+    started: Instant,
+    expr: usize,
+    defs_entered: Vec<usize>,
+    /// Totals across every call of `eval_all`.
+    pub stats: CacheStats,
+}
+
+impl<'a> MemoEvaluator<'a> {
+    pub fn new(dag: &'a ProgramDag) -> Self {
+        Self::with_limits(dag, Limits::default())
+    }
+
+    /// An evaluator stopping with `EvalError::ResourceExhausted` as soon as
+    /// any of `limits` is exceeded, which each call of `eval_all` starts
+    /// afresh.
+    pub fn with_limits(dag: &'a ProgramDag, limits: Limits) -> Self {
+        let mut def_indices: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, (name, _)) in dag.defs.iter().enumerate() {
+            def_indices.entry(name).or_default().push(index);
+        }
+        MemoEvaluator {
+            dag,
+            limits,
+            def_indices,
+            values: HashMap::new(),
+            steps: 0,
+            started: Instant::now(),
+            expr: 0,
+            defs_entered: Vec::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Evaluates each expression of the program, in program order, looking up
+    /// free variables in `env`. Values cached under a previous assignment are
+    /// discarded.
+    pub fn eval_all(&mut self, env: &Env) -> Result<Vec<bool>, EvalError> {
+        self.values.clear();
+        self.steps = 0;
+        self.started = Instant::now();
+        let dag = self.dag;
+        let scope = dag.defs.len();
+        dag.exprs
+            .iter()
+            .enumerate()
+            .map(|(index, id)| {
+                self.expr = index;
+                self.defs_entered.clear();
+                self.eval(*id, env, 0, scope)
+            })
+            .collect()
+    }
+
+    fn exhausted(&self, limit: Limit, id: NodeId) -> EvalError {
+        EvalError::ResourceExhausted {
+            limit,
+            expr: self.expr,
+            def: self
+                .defs_entered
+                .last()
+                .map(|&index| self.dag.defs[index].0.clone()),
+            at: self.dag.arena.to_expr(id),
+        }
+    }
+
+    fn tick(&mut self, id: NodeId, depth: usize) -> Result<(), EvalError> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps.filter(|&max| self.steps > max) {
+            return Err(self.exhausted(Limit::Steps(max), id));
+        }
+        if let Some(max) = self.limits.max_depth.filter(|&max| depth > max) {
+            return Err(self.exhausted(Limit::Depth(max), id));
+        }
+        if let Some(timeout) = self.limits.timeout {
+            if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && self.started.elapsed() > timeout
+            {
+                return Err(self.exhausted(Limit::Timeout(timeout), id));
             }
-            BinOp::And => {
-                let v1 = evaluate_expr((*arg1).clone());
-                let v2 = evaluate_expr((*arg2).clone());
-                match (v1, v2) {
-                    (Some(v1), Some(v2)) => Some(v1 && v2),
-                    _ => None,
+        }
+        Ok(())
+    }
+
+    /// The latest definition of `name` among the first `scope`.
+    fn lookup(&self, name: &str, scope: usize) -> Option<usize> {
+        let indices = self.def_indices.get(name)?;
+        let visible = indices.partition_point(|&index| index < scope);
+        visible.checked_sub(1).map(|last| indices[last])
+    }
+
+    /// Evaluates the node `id` seeing only the first `scope` definitions.
+    fn eval(
+        &mut self,
+        id: NodeId,
+        env: &Env,
+        depth: usize,
+        scope: usize,
+    ) -> Result<bool, EvalError> {
+        self.tick(id, depth)?;
+        if let Some(value) = self.values.get(&(id, scope)) {
+            self.stats.hits += 1;
+            return Ok(*value);
+        }
+        self.stats.misses += 1;
+        let dag = self.dag;
+        let value = match dag.arena.node(id) {
+            Node::Value(v) => *v,
+            Node::Unknown => return Err(EvalError::Unknown),
+            Node::Var(name) => match self.lookup(name, scope) {
+                Some(index) => {
+                    self.defs_entered.push(index);
+                    let value = self.eval(dag.defs[index].1, env, depth + 1, index)?;
+                    self.defs_entered.pop();
+                    value
                 }
+                None => env
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| EvalError::Unbound(name.clone()))?,
+            },
+            Node::Unary { op, arg } => match op {
+                UnOp::Not => !self.eval(*arg, env, depth + 1, scope)?,
+            },
+            Node::Binary { op, arg1, arg2 } => {
+                let v1 = self.eval(*arg1, env, depth + 1, scope)?;
+                let v2 = self.eval(*arg2, env, depth + 1, scope)?;
+                apply_binop(*op, v1, v2)
             }
-            _ => None,
-        },
+            Node::And(args) => args.iter().try_fold(true, |acc, arg| {
+                self.eval(*arg, env, depth + 1, scope).map(|v| acc && v)
+            })?,
+            Node::Or(args) => args.iter().try_fold(false, |acc, arg| {
+                self.eval(*arg, env, depth + 1, scope).map(|v| acc || v)
+            })?,
+            Node::Ternary { cond, then, else_ } => match self.eval(*cond, env, depth + 1, scope)? {
+                true => self.eval(*then, env, depth + 1, scope)?,
+                false => self.eval(*else_, env, depth + 1, scope)?,
+            },
+        };
+        self.values.insert((id, scope), value);
+        Ok(value)
     }
 }
 
-pub fn evaluate_program(program: Program) -> Option<BigUint> {
-    program
-        .exprs
-        .iter()
-        .map(|expr| evaluate_expr(expr.clone()))
-        .fold(Some(BigUint::from(0u32)), |acc, v| match (acc, v) {
-            (Some(b), Some(true)) => Some(2u32 * b + 1u32),
-            (Some(b), Some(false)) => Some(2u32 * b),
-            (_, _) => None,
+/// Evaluates each expression of a program, in program order, looking up
+/// variables which no definition binds in `env`, and stopping with
+/// `EvalError::ResourceExhausted` as soon as any of `limits` is exceeded.
+/// Repeated sub-expressions are evaluated once, as by `MemoEvaluator`.
+pub fn evaluate_bits_with(
+    program: &Program,
+    env: &Env,
+    limits: Limits,
+) -> Result<Vec<bool>, EvalError> {
+    MemoEvaluator::with_limits(&ProgramDag::from_program(program), limits).eval_all(env)
+}
+
+/// A truth value of Kleene's strong three-valued logic, in which `Unknown`
+/// stands for a value that could turn out to be either. Values are ordered
+/// `False < Unknown < True`, so `&` is the minimum and `|` the maximum.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum Kleene {
+    False,
+    Unknown,
+    True,
+}
+
+impl Kleene {
+    /// The two-valued truth value, if it is determined.
+    pub fn known(self) -> Option<bool> {
+        match self {
+            Kleene::False => Some(false),
+            Kleene::Unknown => None,
+            Kleene::True => Some(true),
+        }
+    }
+
+    pub fn and(self, other: Kleene) -> Kleene {
+        self.min(other)
+    }
+
+    pub fn or(self, other: Kleene) -> Kleene {
+        self.max(other)
+    }
+
+    /// `self ? then : else_`. An unknown condition still determines the
+    /// result when both branches agree.
+    pub fn ite(self, then: Kleene, else_: Kleene) -> Kleene {
+        match self.known() {
+            Some(true) => then,
+            Some(false) => else_,
+            None if then == else_ => then,
+            None => Kleene::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Kleene {
+    fn from(value: bool) -> Self {
+        match value {
+            true => Kleene::True,
+            false => Kleene::False,
+        }
+    }
+}
+
+impl From<Kleene> for Expr {
+    fn from(value: Kleene) -> Self {
+        match value.known() {
+            Some(v) => Expr::Value(v),
+            None => Expr::Unknown,
+        }
+    }
+}
+
+impl Not for Kleene {
+    type Output = Kleene;
+
+    fn not(self) -> Kleene {
+        match self {
+            Kleene::False => Kleene::True,
+            Kleene::Unknown => Kleene::Unknown,
+            Kleene::True => Kleene::False,
+        }
+    }
+}
+
+impl fmt::Display for Kleene {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kleene::False => "f",
+            Kleene::Unknown => "u",
+            Kleene::True => "t",
         })
+    }
+}
+
+/// `apply_binop` in three-valued logic, with each operator defined through
+/// `~`, `&` and `|` as in two-valued logic.
+pub fn apply_kleene_binop(op: BinOp, k1: Kleene, k2: Kleene) -> Kleene {
+    match op {
+        BinOp::Or => k1.or(k2),
+        BinOp::And => k1.and(k2),
+        BinOp::Xor => k1.or(k2).and(!k1.and(k2)),
+        BinOp::Nand => !k1.and(k2),
+        BinOp::Nor => !k1.or(k2),
+        BinOp::Implies => (!k1).or(k2),
+        BinOp::Iff => !apply_kleene_binop(BinOp::Xor, k1, k2),
+    }
+}
+
+/// Evaluates each expression of a program, in program order, in three-valued
+/// logic. Variables which neither a definition nor `env` binds, and `u`, are
+/// unknown, so evaluation cannot fail.
+pub fn evaluate_kleene(program: &Program, env: &Env) -> Vec<Kleene> {
+    let dag = ProgramDag::inlined(program);
+    // operands are always added to the arena before the nodes using them
+    let mut values: Vec<Kleene> = Vec::with_capacity(dag.arena.len());
+    for node in dag.arena.nodes() {
+        let value = match node {
+            Node::Value(v) => Kleene::from(*v),
+            Node::Unknown => Kleene::Unknown,
+            Node::Var(name) => env.get(name).map_or(Kleene::Unknown, |v| Kleene::from(*v)),
+            Node::Unary { op, arg } => match op {
+                UnOp::Not => !values[arg.index()],
+            },
+            Node::Binary { op, arg1, arg2 } => {
+                apply_kleene_binop(*op, values[arg1.index()], values[arg2.index()])
+            }
+            Node::And(args) => args
+                .iter()
+                .fold(Kleene::True, |acc, arg| acc.and(values[arg.index()])),
+            Node::Or(args) => args
+                .iter()
+                .fold(Kleene::False, |acc, arg| acc.or(values[arg.index()])),
+            Node::Ternary { cond, then, else_ } => {
+                values[cond.index()].ite(values[then.index()], values[else_.index()])
+            }
+        };
+        values.push(value);
+    }
+    dag.exprs.iter().map(|id| values[id.index()]).collect()
+}
+
+/// Evaluates a single expression in three-valued logic, as `evaluate_kleene`.
+pub fn evaluate_kleene_expr(expr: &Expr, env: &Env) -> Kleene {
+    let program = Program {
+        defs: Vec::new(),
+        exprs: vec![expr.clone()],
+    };
+    evaluate_kleene(&program, env)[0]
+}
+
+/// Packs a sequence of truth values into an unsigned integer, one bit each.
+pub fn pack_bits(bits: &[bool], order: BitOrder) -> BigUint {
+    let fold = |acc: BigUint, bit: &bool| 2u32 * acc + u32::from(*bit);
+    match order {
+        BitOrder::Msb => bits.iter().fold(BigUint::from(0u32), fold),
+        BitOrder::Lsb => bits.iter().rev().fold(BigUint::from(0u32), fold),
+    }
+}
+
+/// Unpacks an unsigned integer into at least `width` truth values, the
+/// inverse of `pack_bits`. The value is never truncated, so the result may be
+/// longer than `width`; it always holds at least one bit.
+pub fn unpack_bits(value: &BigUint, width: usize, order: BitOrder) -> Vec<bool> {
+    let len = (value.bits() as usize).max(width).max(1);
+    let lsb_first = (0..len as u64).map(|i| value.bit(i));
+    match order {
+        BitOrder::Msb => lsb_first.rev().collect(),
+        BitOrder::Lsb => lsb_first.collect(),
+    }
+}
+
+pub fn evaluate_program(program: Program) -> Option<BigUint> {
+    evaluate_bits(program).map(|bits| pack_bits(&bits, BitOrder::Msb))
 }
@@ -1,10 +1,62 @@
+use std::fmt;
+
 use crate::blambda::serde_ast;
-use crate::blambda::syntax::{Expr, Program};
+use crate::blambda::syntax::{Definition, Expr, Program};
+
+/// Spelling used for operators and values when formatting a program.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Notation {
+    /// `~`, `&`, `|`, `t`, `f`, ...
+    #[default]
+    Ascii,
+    /// `¬`, `∧`, `∨`, `⊤`, `⊥`, ...
+    Unicode,
+    /// `not`, `and`, `or`, `true`, `false`, ...
+    Keyword,
+}
 
 pub fn format_expr(expr: &Expr) -> String {
-    serde_ast::serialize_expr(expr).unwrap()
+    format_expr_as(expr, Notation::Ascii)
+}
+
+pub fn format_expr_as(expr: &Expr, notation: Notation) -> String {
+    serde_ast::serialize_expr_as(expr, notation).unwrap()
 }
 
 pub fn format_program(program: &Program) -> String {
-    serde_ast::serialize_program(program).unwrap()
+    format_program_as(program, Notation::Ascii)
+}
+
+pub fn format_program_as(program: &Program, notation: Notation) -> String {
+    serde_ast::serialize_program_as(program, notation).unwrap()
+}
+
+impl Program {
+    /// Formats the program in ASCII notation, fully parenthesized.
+    ///
+    /// ```
+    /// let program = blambda::parse_str("x=t&f x|~x").unwrap();
+    /// assert_eq!(program.format(), "x = (t & f) (x | (~ x))");
+    /// ```
+    pub fn format(&self) -> String {
+        format_program(self)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_expr(self))
+    }
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.name, self.body)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_program(self))
+    }
 }
@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Formatting, GotoDefinition, HoverRequest, Request as LspRequest, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, SemanticToken,
+    SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Uri,
+};
+use pest::iterators::{Pair, Pairs};
+use pest::Parser;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::blambda::error::BlambdaError;
+use crate::blambda::eval::{evaluate_kleene_expr, truth_table, Env};
+use crate::blambda::format::{format_expr, format_program};
+use crate::blambda::normalize::{free_vars, inline_definitions};
+use crate::blambda::parse::{parse_program, BlambdaParser, Rule};
+use crate::blambda::syntax::{Expr, Program};
+
+/// Hovering an expression with more free variables than this shows their
+/// names rather than a truth table.
+const MAX_HOVER_TABLE_VARS: usize = 5;
+
+// indices into the semantic token legend below
+const OPERATOR_TOKEN: u32 = 0;
+const LITERAL_TOKEN: u32 = 1;
+const VARIABLE_TOKEN: u32 = 2;
+const DECLARATION_MODIFIER: u32 = 1 << 0;
+
+fn semantic_tokens_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::OPERATOR,
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::VARIABLE,
+        ],
+        token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+    }
+}
+
+/// Converts between byte offsets into a document and LSP positions, whose
+/// characters are counted in UTF-16 code units.
+struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex { text, line_starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (index, c) in self.text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + index;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn range(&self, span: Range<usize>) -> lsp_types::Range {
+        lsp_types::Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+fn span(pair: &Pair<Rule>) -> Range<usize> {
+    pair.as_span().start()..pair.as_span().end()
+}
+
+/// The definitions and expressions of a parsed program, in source order.
+fn items(pairs: Pairs<Rule>) -> Vec<Pair<Rule>> {
+    pairs
+        .flat_map(|pair| pair.into_inner())
+        .filter(|pair| matches!(pair.as_rule(), Rule::definition | Rule::expr))
+        .collect()
+}
+
+/// The innermost expression, variable or value within `pair` which covers
+/// `offset`.
+fn innermost(pair: Pair<Rule>, offset: usize) -> Pair<Rule> {
+    match pair.clone().into_inner().find(|child| {
+        matches!(
+            child.as_rule(),
+            Rule::expr | Rule::ident | Rule::boolval | Rule::unknown
+        ) && span(child).contains(&offset)
+    }) {
+        Some(child) => innermost(child, offset),
+        None => pair,
+    }
+}
+
+/// Parses the source of a sub-expression on its own, which must be exactly
+/// one expression.
+fn reparse_expr(source: &str) -> Option<Expr> {
+    source.parse().ok()
+}
+
+/// Parse errors, or warnings for variables which no earlier definition binds
+/// and which therefore keep an expression from being evaluated.
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let index = LineIndex::new(text);
+    let error_diagnostic = |error: BlambdaError| {
+        let (range, message) = match error.location() {
+            Some(location) => (index.range(location.span.clone()), location.reason.clone()),
+            None => (index.range(0..0), format!("{}", error)),
+        };
+        Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("blambda".to_string()),
+            message,
+            ..Default::default()
+        }
+    };
+
+    let pairs = match BlambdaParser::parse(Rule::program, text) {
+        Ok(pairs) => pairs,
+        Err(error) => return vec![error_diagnostic(error.into())],
+    };
+    if let Err(error) = parse_program(pairs.clone()) {
+        return vec![error_diagnostic(error)];
+    }
+
+    let mut defined: Vec<&str> = Vec::new();
+    let mut diagnostics = Vec::new();
+    for item in items(pairs) {
+        let mut inner = item.clone().into_inner();
+        let (name, body) = match item.as_rule() {
+            Rule::definition => (inner.next(), inner.next().unwrap()),
+            _ => (None, item),
+        };
+        for var in body.into_inner().flatten() {
+            if var.as_rule() == Rule::ident && !defined.contains(&var.as_str()) {
+                diagnostics.push(Diagnostic {
+                    range: index.range(span(&var)),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    source: Some("blambda".to_string()),
+                    message: format!(
+                        "`{}` is not defined by an earlier definition, so it cannot be evaluated",
+                        var.as_str()
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+        defined.extend(name.map(|name| name.as_str()));
+    }
+    diagnostics
+}
+
+/// The value of the hovered sub-expression, or its truth table if it has free
+/// variables, with `u` where a row's value is unknown. Hovering the name of a
+/// definition describes its body.
+fn hover(text: &str, offset: usize) -> Option<Hover> {
+    let pairs = BlambdaParser::parse(Rule::program, text).ok()?;
+    let program = parse_program(pairs.clone()).ok()?;
+    let items = items(pairs);
+    let position = items.iter().position(|item| span(item).contains(&offset))?;
+    let defs_before = items[..position]
+        .iter()
+        .filter(|item| item.as_rule() == Rule::definition)
+        .count();
+
+    let mut hovered = innermost(items[position].clone(), offset);
+    if hovered.as_rule() == Rule::definition {
+        return None;
+    }
+    if items[position].as_rule() == Rule::definition
+        && hovered.as_rule() == Rule::ident
+        && hovered.as_span().start() == items[position].as_span().start()
+    {
+        hovered = items[position].clone().into_inner().nth(1).unwrap();
+    }
+    let expr = reparse_expr(hovered.as_str())?;
+    let resolved = inline_definitions(&Program {
+        defs: program.defs[..defs_before].to_vec(),
+        exprs: vec![expr.clone()],
+    })
+    .remove(0);
+
+    let repr = |value: bool| if value { "t" } else { "f" };
+    let formatted = format_expr(&expr);
+    let vars = free_vars(&resolved);
+    let description = if vars.is_empty() {
+        format!("= {}", evaluate_kleene_expr(&resolved, &Env::new()))
+    } else if vars.len() > MAX_HOVER_TABLE_VARS {
+        format!(
+            "depends on {} free variables: {}",
+            vars.len(),
+            vars.join(", ")
+        )
+    } else {
+        // with `u` there is no truth table, but each row still has a Kleene value
+        let rows: Vec<(Vec<bool>, String)> = match truth_table(&resolved) {
+            Ok(table) => table
+                .rows
+                .into_iter()
+                .map(|(inputs, output)| (inputs, repr(output).to_string()))
+                .collect(),
+            Err(_) => (0..1u64 << vars.len())
+                .map(|row| {
+                    let inputs: Vec<bool> = (0..vars.len())
+                        .map(|i| row & (1 << (vars.len() - 1 - i)) != 0)
+                        .collect();
+                    let env: Env = vars.iter().cloned().zip(inputs.iter().copied()).collect();
+                    let output = evaluate_kleene_expr(&resolved, &env).to_string();
+                    (inputs, output)
+                })
+                .collect(),
+        };
+        let mut lines = vec![
+            format!("| {} | `{}` |", vars.join(" | "), formatted),
+            format!("|{}", "---|".repeat(vars.len() + 1)),
+        ];
+        lines.extend(rows.iter().map(|(inputs, output)| {
+            let inputs: Vec<&str> = inputs.iter().map(|input| repr(*input)).collect();
+            format!("| {} | {} |", inputs.join(" | "), output)
+        }));
+        lines.join("\n")
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```blambda\n{}\n```\n{}", formatted, description),
+        }),
+        range: Some(LineIndex::new(text).range(span(&hovered))),
+    })
+}
+
+/// The span of the name of the definition which the variable at `offset`
+/// refers to: the last one of that name before the item using it. The name of
+/// a definition refers to itself.
+fn definition(text: &str, offset: usize) -> Option<Range<usize>> {
+    let pairs = BlambdaParser::parse(Rule::program, text).ok()?;
+    let items = items(pairs);
+    let position = items.iter().position(|item| span(item).contains(&offset))?;
+    let var = innermost(items[position].clone(), offset);
+    if var.as_rule() != Rule::ident {
+        return None;
+    }
+    if items[position].as_rule() == Rule::definition
+        && var.as_span().start() == items[position].as_span().start()
+    {
+        return Some(span(&var));
+    }
+    items[..position]
+        .iter()
+        .rev()
+        .filter(|item| item.as_rule() == Rule::definition)
+        .map(|item| item.clone().into_inner().next().unwrap())
+        .find(|name| name.as_str() == var.as_str())
+        .map(|name| span(&name))
+}
+
+fn semantic_tokens(text: &str) -> Vec<SemanticToken> {
+    let Ok(pairs) = BlambdaParser::parse(Rule::program, text) else {
+        return Vec::new();
+    };
+    let index = LineIndex::new(text);
+    let declarations: Vec<usize> = items(pairs.clone())
+        .iter()
+        .filter(|item| item.as_rule() == Rule::definition)
+        .map(|item| item.as_span().start())
+        .collect();
+
+    let mut tokens = Vec::new();
+    let mut previous = Position::new(0, 0);
+    for pair in pairs.flatten() {
+        let (token_type, modifiers) = match pair.as_rule() {
+            Rule::or
+            | Rule::and
+            | Rule::xor
+            | Rule::nand
+            | Rule::nor
+            | Rule::implies
+            | Rule::iff
+            | Rule::condition
+            | Rule::branch
+            | Rule::neg => (OPERATOR_TOKEN, 0),
+            Rule::boolval | Rule::unknown => (LITERAL_TOKEN, 0),
+            Rule::ident if declarations.contains(&pair.as_span().start()) => {
+                (VARIABLE_TOKEN, DECLARATION_MODIFIER)
+            }
+            Rule::ident => (VARIABLE_TOKEN, 0),
+            _ => continue,
+        };
+        // tokens are relative to the previous one, as the protocol requires
+        let start = index.position(pair.as_span().start());
+        let delta_start = if start.line == previous.line {
+            start.character - previous.character
+        } else {
+            start.character
+        };
+        tokens.push(SemanticToken {
+            delta_line: start.line - previous.line,
+            delta_start,
+            length: pair.as_str().encode_utf16().count() as u32,
+            token_type,
+            token_modifiers_bitset: modifiers,
+        });
+        previous = start;
+    }
+    tokens
+}
+
+/// Replaces the whole document with its `format_program` form.
+fn formatting(text: &str) -> Option<Vec<TextEdit>> {
+    let pairs = BlambdaParser::parse(Rule::program, text).ok()?;
+    let program = parse_program(pairs).ok()?;
+    let index = LineIndex::new(text);
+    Some(vec![TextEdit {
+        range: index.range(0..text.len()),
+        new_text: format!("{}\n", format_program(&program)),
+    }])
+}
+
+/// The open documents of a language server session.
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<Uri, String>,
+}
+
+impl Server {
+    fn document(&self, uri: &Uri) -> &str {
+        self.documents.get(uri).map_or("", String::as_str)
+    }
+
+    /// Handles a request, replying with an error for unsupported methods or
+    /// malformed parameters.
+    fn handle_request(&self, request: Request) -> Response {
+        fn reply<P: DeserializeOwned, R: Serialize>(
+            request: Request,
+            handler: impl FnOnce(P) -> R,
+        ) -> Response {
+            match serde_json::from_value(request.params) {
+                Ok(params) => Response::new_ok(request.id, handler(params)),
+                Err(e) => {
+                    Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string())
+                }
+            }
+        }
+
+        match request.method.as_str() {
+            HoverRequest::METHOD => reply(request, |params: HoverParams| {
+                let position = params.text_document_position_params;
+                let text = self.document(&position.text_document.uri);
+                hover(text, LineIndex::new(text).offset(position.position))
+            }),
+            GotoDefinition::METHOD => reply(request, |params: GotoDefinitionParams| {
+                let position = params.text_document_position_params;
+                let text = self.document(&position.text_document.uri);
+                let index = LineIndex::new(text);
+                definition(text, index.offset(position.position)).map(|span| {
+                    GotoDefinitionResponse::Scalar(Location::new(
+                        position.text_document.uri.clone(),
+                        index.range(span),
+                    ))
+                })
+            }),
+            Formatting::METHOD => reply(request, |params: DocumentFormattingParams| {
+                formatting(self.document(&params.text_document.uri))
+            }),
+            SemanticTokensFullRequest::METHOD => {
+                reply(request, |params: SemanticTokensParams| SemanticTokens {
+                    result_id: None,
+                    data: semantic_tokens(self.document(&params.text_document.uri)),
+                })
+            }
+            method => Response::new_err(
+                request.id.clone(),
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request `{}`", method),
+            ),
+        }
+    }
+
+    /// Tracks the open documents, returning the diagnostics to publish for a
+    /// document which was opened, changed or closed.
+    fn handle_notification(&mut self, notification: Notification) -> Option<Notification> {
+        let (uri, diagnostics) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                let diagnostics = diagnostics(&params.text_document.text);
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                (uri, diagnostics)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                // the server only asks for full document sync
+                let text = params.content_changes.into_iter().last()?.text;
+                let diagnostics = diagnostics(&text);
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), text);
+                (uri, diagnostics)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&params.text_document.uri);
+                (params.text_document.uri, Vec::new())
+            }
+            _ => return None,
+        };
+        Some(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            PublishDiagnosticsParams::new(uri, diagnostics, None),
+        ))
+    }
+}
+
+fn send(connection: &Connection, message: Message) -> Result<(), BlambdaError> {
+    connection
+        .sender
+        .send(message)
+        .map_err(|e| BlambdaError::new(format!("{}", e)))
+}
+
+/// Serves a language server session over `connection` until the client shuts
+/// it down.
+pub fn serve(connection: &Connection) -> Result<(), BlambdaError> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: semantic_tokens_legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                send(connection, server.handle_request(request).into())?;
+            }
+            Message::Notification(notification) => {
+                if let Some(publish) = server.handle_notification(notification) {
+                    send(connection, publish.into())?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Runs a language server over stdio.
+pub fn run() -> Result<(), BlambdaError> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
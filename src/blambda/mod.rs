@@ -1,6 +1,29 @@
+#[cfg(feature = "cli")]
+pub mod assign;
+#[cfg(feature = "cli")]
+pub mod batch;
+#[cfg(feature = "cli")]
+pub mod bdd;
+pub mod compile;
+pub mod dag;
+#[cfg(feature = "cli")]
+pub mod encoding;
 pub mod error;
 pub mod eval;
 pub mod format;
+#[cfg(feature = "cli")]
+pub mod lsp;
+pub mod normalize;
 pub mod parse;
+#[cfg(feature = "cli")]
+pub mod reduce;
+#[cfg(feature = "cli")]
+pub mod repl;
 pub mod serde_ast;
+#[cfg(feature = "cli")]
+pub mod specialize;
 pub mod syntax;
+#[cfg(feature = "cli")]
+pub mod synth;
+#[cfg(feature = "cli")]
+pub mod trace;
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blambda::syntax::{BinOp, Definition, Expr, Fold, Program, Visit};
+
+/// Flattens chains of `&` and `|` into n-ary `Expr::And`/`Expr::Or` nodes, so
+/// `a & b & c & d` becomes a single node with four operands rather than a
+/// left-leaning spine of binaries. Chains are collected iteratively, so the
+/// length of a chain does not affect stack depth.
+pub fn flatten_expr(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Value(_) | Expr::Unknown | Expr::Var(_) => expr.clone(),
+        Expr::Unary { op, arg } => Expr::Unary {
+            op: *op,
+            arg: Arc::new(flatten_expr(arg)),
+        },
+        Expr::Binary { op: BinOp::And, .. } | Expr::And(_) => {
+            Expr::And(flatten_chain(expr, BinOp::And))
+        }
+        Expr::Binary { op: BinOp::Or, .. } | Expr::Or(_) => {
+            Expr::Or(flatten_chain(expr, BinOp::Or))
+        }
+        Expr::Binary { op, arg1, arg2 } => Expr::Binary {
+            op: *op,
+            arg1: Arc::new(flatten_expr(arg1)),
+            arg2: Arc::new(flatten_expr(arg2)),
+        },
+        Expr::Ternary { cond, then, else_ } => Expr::Ternary {
+            cond: Arc::new(flatten_expr(cond)),
+            then: Arc::new(flatten_expr(then)),
+            else_: Arc::new(flatten_expr(else_)),
+        },
+    }
+}
+
+/// Collects the operands of a chain of `op` nodes, left to right.
+fn flatten_chain(expr: &Expr, op: BinOp) -> Vec<Expr> {
+    let mut operands = Vec::new();
+    let mut stack = vec![expr];
+    while let Some(node) = stack.pop() {
+        match (node, op) {
+            (
+                Expr::Binary {
+                    op: inner,
+                    arg1,
+                    arg2,
+                },
+                _,
+            ) if *inner == op => {
+                stack.push(arg2);
+                stack.push(arg1);
+            }
+            (Expr::And(args), BinOp::And) | (Expr::Or(args), BinOp::Or) => {
+                stack.extend(args.iter().rev());
+            }
+            (other, _) => operands.push(flatten_expr(other)),
+        }
+    }
+    operands
+}
+
+pub fn flatten_program(program: &Program) -> Program {
+    Program {
+        defs: program
+            .defs
+            .iter()
+            .map(|def| Definition {
+                name: def.name.clone(),
+                body: flatten_expr(&def.body),
+            })
+            .collect(),
+        exprs: program.exprs.iter().map(flatten_expr).collect(),
+    }
+}
+
+/// Lists the variables of an expression, in order of first appearance.
+pub fn free_vars(expr: &Expr) -> Vec<String> {
+    struct Vars(Vec<String>);
+
+    impl Visit for Vars {
+        fn visit_var(&mut self, name: &str) {
+            if !self.0.iter().any(|var| var == name) {
+                self.0.push(name.to_string());
+            }
+        }
+    }
+
+    let mut vars = Vars(Vec::new());
+    vars.walk(expr);
+    vars.0
+}
+
+/// Whether an expression contains the unknown value `u` anywhere.
+pub fn has_unknown(expr: &Expr) -> bool {
+    struct HasUnknown(bool);
+
+    impl Visit for HasUnknown {
+        fn visit_unknown(&mut self) {
+            self.0 = true;
+        }
+    }
+
+    let mut visitor = HasUnknown(false);
+    visitor.walk(expr);
+    visitor.0
+}
+
+/// Replaces each variable bound in `bindings` by its expression.
+pub fn substitute(expr: &Expr, bindings: &HashMap<String, Expr>) -> Expr {
+    struct Substitute<'a>(&'a HashMap<String, Expr>);
+
+    impl Fold for Substitute<'_> {
+        fn fold_var(&mut self, name: &str) -> Expr {
+            self.0.get(name).cloned().unwrap_or_else(|| Expr::var(name))
+        }
+    }
+
+    Substitute(bindings).fold(expr)
+}
+
+/// Substitutes a program's definitions into its expressions. Each definition
+/// sees only the definitions before it; any other variable is left free.
+pub fn inline_definitions(program: &Program) -> Vec<Expr> {
+    let mut bindings: HashMap<String, Expr> = HashMap::new();
+    for def in &program.defs {
+        let body = substitute(&def.body, &bindings);
+        bindings.insert(def.name.clone(), body);
+    }
+    program
+        .exprs
+        .iter()
+        .map(|expr| substitute(expr, &bindings))
+        .collect()
+}
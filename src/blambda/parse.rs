@@ -1,7 +1,11 @@
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
-use crate::blambda::syntax::{BinOp, Expr, Program, UnOp};
-use pest::iterators::Pairs;
+use crate::blambda::error::BlambdaError;
+use crate::blambda::syntax::{BinOp, Definition, Expr, Program, UnOp};
+use pest::error::{Error, ErrorVariant};
+use pest::iterators::{Pair, Pairs};
 use pest::pratt_parser::PrattParser;
 #[allow(unused_imports)]
 use pest::Parser;
@@ -11,11 +15,18 @@ lazy_static::lazy_static! {
         use pest::pratt_parser::{Assoc::*, Op};
         use Rule::*;
 
+        // the ternary operator binds loosest of all, but is split out by
+        // `parse_expr` before the remaining operators reach this table
         PrattParser::new()
-            // lowest precidence is ternary operator components
-            .op(Op::infix(condition, Right) | Op::infix(branch, Left))
-            // second-lowest precedence is binary ops
-            .op(Op::infix(or, Left) | Op::infix(and, Left))
+            // biconditional binds looser than implication, which is right-associative
+            .op(Op::infix(iff, Left))
+            .op(Op::infix(implies, Right))
+            // remaining binary ops share a precedence level
+            .op(Op::infix(or, Left)
+                | Op::infix(and, Left)
+                | Op::infix(xor, Left)
+                | Op::infix(nand, Left)
+                | Op::infix(nor, Left))
             // highest precedence is unary op
             .op(Op::prefix(neg))
     };
@@ -25,10 +36,96 @@ lazy_static::lazy_static! {
 #[grammar = "grammar/blambda.pest"]
 pub struct BlambdaParser;
 
-pub fn parse_expr(pairs: Pairs<Rule>) -> Expr {
+pub fn parse_expr(pairs: Pairs<Rule>) -> Result<Expr, BlambdaError> {
+    let tokens: Vec<Pair<Rule>> = pairs.collect();
+    parse_ternary(&tokens)
+}
+
+/// Splits a flat run of expression tokens on its outermost `cond ? then : else`,
+/// pairing each `?` with the nearest unmatched `:` as in C. The form
+/// `cond ? (then : else)`, which earlier releases formatted ternaries as, is
+/// accepted too.
+fn parse_ternary(tokens: &[Pair<Rule>]) -> Result<Expr, BlambdaError> {
+    let Some(question) = tokens
+        .iter()
+        .position(|token| matches!(token.as_rule(), Rule::condition | Rule::branch))
+    else {
+        return parse_operators(tokens);
+    };
+    if tokens[question].as_rule() == Rule::branch {
+        return Err(custom_error(
+            &tokens[question],
+            "`:` without a preceding `?`; ternary expressions are written `cond ? then : else`",
+        ));
+    }
+
+    let mut depth = 0usize;
+    let colon = tokens
+        .iter()
+        .enumerate()
+        .skip(question + 1)
+        .find_map(|(index, token)| match token.as_rule() {
+            Rule::condition => {
+                depth += 1;
+                None
+            }
+            Rule::branch if depth == 0 => Some(index),
+            Rule::branch => {
+                depth -= 1;
+                None
+            }
+            _ => None,
+        });
+    let cond = Arc::new(parse_operators(&tokens[..question])?);
+    let (then, else_) = match colon {
+        Some(colon) => (
+            parse_ternary(&tokens[question + 1..colon])?,
+            parse_ternary(&tokens[colon + 1..])?,
+        ),
+        None => legacy_branches(&tokens[question + 1..]).ok_or_else(|| {
+            custom_error(
+                &tokens[question],
+                "`?` without a matching `:`; ternary expressions are written `cond ? then : else`",
+            )
+        })??,
+    };
+
+    Ok(Expr::Ternary {
+        cond,
+        then: Arc::new(then),
+        else_: Arc::new(else_),
+    })
+}
+
+/// The branches of a legacy ternary, whose `then : else` follow the `?` as a
+/// single parenthesised expression with a `:` before any `?`.
+fn legacy_branches(tokens: &[Pair<Rule>]) -> Option<Result<(Expr, Expr), BlambdaError>> {
+    let [branches] = tokens else {
+        return None;
+    };
+    if branches.as_rule() != Rule::expr {
+        return None;
+    }
+    let inner: Vec<Pair<Rule>> = branches.clone().into_inner().collect();
+    let colon = inner
+        .iter()
+        .position(|token| matches!(token.as_rule(), Rule::condition | Rule::branch))
+        .filter(|&index| inner[index].as_rule() == Rule::branch)?;
+    Some(parse_ternary(&inner[..colon]).and_then(|then| {
+        let else_ = parse_ternary(&inner[colon + 1..])?;
+        Ok((then, else_))
+    }))
+}
+
+fn parse_operators(tokens: &[Pair<Rule>]) -> Result<Expr, BlambdaError> {
     PRATT_PARSER
         .map_primary(|primary| match primary.as_rule() {
-            Rule::boolval => Expr::Value(primary.as_str().to_lowercase() == "t"),
+            Rule::boolval => Ok(Expr::Value(matches!(
+                primary.as_str().to_lowercase().as_str(),
+                "t" | "true" | "⊤"
+            ))),
+            Rule::unknown => Ok(Expr::Unknown),
+            Rule::ident => Ok(Expr::Var(name(&primary)?)),
             Rule::expr => parse_expr(primary.into_inner()),
             rule => unreachable!("Expr::parse expected atom, got {:?}", rule),
         })
@@ -36,35 +133,181 @@ pub fn parse_expr(pairs: Pairs<Rule>) -> Expr {
             let op = match op.as_rule() {
                 Rule::or => BinOp::Or,
                 Rule::and => BinOp::And,
-                Rule::condition => BinOp::Condition,
-                Rule::branch => BinOp::Branch,
+                Rule::xor => BinOp::Xor,
+                Rule::nand => BinOp::Nand,
+                Rule::nor => BinOp::Nor,
+                Rule::implies => BinOp::Implies,
+                Rule::iff => BinOp::Iff,
                 _ => unreachable!(),
             };
-            Expr::Binary {
+            Ok(Expr::Binary {
                 op,
-                arg1: Arc::new(lhs),
-                arg2: Arc::new(rhs),
-            }
+                arg1: Arc::new(lhs?),
+                arg2: Arc::new(rhs?),
+            })
         })
         .map_prefix(|op, arg| {
             let op = match op.as_rule() {
                 Rule::neg => UnOp::Not,
                 _ => unreachable!(),
             };
-            Expr::Unary {
+            Ok(Expr::Unary {
                 op,
-                arg: Arc::new(arg),
-            }
+                arg: Arc::new(arg?),
+            })
         })
-        .parse(pairs)
+        .parse(tokens.iter().cloned())
+}
+
+fn custom_error(token: &Pair<Rule>, message: &str) -> BlambdaError {
+    Error::new_from_span(
+        ErrorVariant::<Rule>::CustomError {
+            message: message.to_string(),
+        },
+        token.as_span(),
+    )
+    .into()
+}
+
+/// Whether `name` is only the values `t` and `f` run together, which releases
+/// before variables read as several values, e.g. `tf` as `t f`.
+fn is_run_together_values(name: &str) -> bool {
+    name.len() > 1 && name.chars().all(|c| matches!(c, 't' | 'T' | 'f' | 'F'))
+}
+
+/// The name an identifier spells, refusing values run together so that old
+/// programs relying on them fail rather than silently change meaning.
+fn name(ident: &Pair<Rule>) -> Result<String, BlambdaError> {
+    let name = ident.as_str();
+    if is_run_together_values(name) {
+        let values: Vec<String> = name.chars().map(String::from).collect();
+        return Err(custom_error(
+            ident,
+            &format!(
+                "`{}` is values run together, which are no longer split apart; write `{}`",
+                name,
+                values.join(" ")
+            ),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn parse_definition(pair: Pair<Rule>) -> Result<Definition, BlambdaError> {
+    let mut inner = pair.into_inner();
+    let name = name(&inner.next().unwrap())?;
+    let body = parse_expr(inner.next().unwrap().into_inner())?;
+    Ok(Definition { name, body })
+}
+
+/// Records the first use in `expr` of each name that none of `defs` binds.
+fn note_free_uses<'i>(
+    defs: &[Definition],
+    expr: &Pair<'i, Rule>,
+    free: &mut HashMap<String, Pair<'i, Rule>>,
+) {
+    for var in expr.clone().into_inner().flatten() {
+        if var.as_rule() == Rule::ident && !defs.iter().any(|def| def.name == var.as_str()) {
+            free.entry(var.as_str().to_string()).or_insert(var);
+        }
+    }
+}
+
+/// Parses a program. A definition only applies to what follows it, so a name
+/// used before it is defined is rejected rather than left ambiguous between
+/// the free variable and the definition.
+pub fn parse_program(pairs: Pairs<Rule>) -> Result<Program, BlambdaError> {
+    let mut program = Program {
+        defs: Vec::new(),
+        exprs: Vec::new(),
+    };
+    let mut free = HashMap::new();
+    for pair in pairs.flat_map(|pair| pair.into_inner()) {
+        match pair.as_rule() {
+            Rule::definition => {
+                let mut inner = pair.clone().into_inner();
+                let name = inner.next().unwrap();
+                if program.defs.iter().any(|def| def.name == name.as_str()) {
+                    return Err(custom_error(
+                        &name,
+                        &format!("`{}` is already defined", name.as_str()),
+                    ));
+                }
+                note_free_uses(&program.defs, &inner.next().unwrap(), &mut free);
+                if let Some(var) = free.get(name.as_str()) {
+                    return Err(custom_error(
+                        var,
+                        &format!(
+                            "`{}` is used before its definition; definitions only apply to what follows them",
+                            name.as_str()
+                        ),
+                    ));
+                }
+                program.defs.push(parse_definition(pair)?);
+            }
+            Rule::expr => {
+                note_free_uses(&program.defs, &pair, &mut free);
+                program.exprs.push(parse_expr(pair.into_inner())?);
+            }
+            _ => (),
+        }
+    }
+    Ok(program)
 }
 
-pub fn parse_program(pairs: Pairs<Rule>) -> Program {
-    Program {
-        exprs: pairs
-            .flat_map(|pair| pair.into_inner())
-            .filter(|pair| pair.as_rule() == Rule::expr)
-            .map(|pair| parse_expr(pair.into_inner()))
-            .collect(),
+/// Parses `input` as `rule`, converting pest's errors into `BlambdaError`s.
+pub fn fallible_parse(rule: Rule, input: &str) -> Result<Pairs<'_, Rule>, BlambdaError> {
+    BlambdaParser::parse(rule, input).map_err(|e| e.into())
+}
+
+/// Whether `name` is a variable name: all of it matches the `ident` rule, so
+/// it is not a keyword such as `t` or `u`, nor values run together like `tf`.
+pub fn is_ident(name: &str) -> bool {
+    BlambdaParser::parse(Rule::ident, name).is_ok_and(|pairs| pairs.as_str() == name)
+        && !is_run_together_values(name)
+}
+
+/// Parses the source of a blambda program.
+///
+/// ```
+/// let program = blambda::parse_str("x = t & f  x | ~x").unwrap();
+/// assert_eq!(program.defs.len(), 1);
+/// assert_eq!(program.exprs.len(), 1);
+///
+/// let error = blambda::parse_str("t &").unwrap_err();
+/// assert_eq!(error.location().unwrap().span, 3..3);
+/// ```
+pub fn parse_str(input: &str) -> Result<Program, BlambdaError> {
+    parse_program(fallible_parse(Rule::program, input)?)
+}
+
+impl FromStr for Program {
+    type Err = BlambdaError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_str(input)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = BlambdaError;
+
+    /// Parses source holding exactly one expression and no definitions.
+    ///
+    /// ```
+    /// use blambda::Expr;
+    ///
+    /// let expr: Expr = "a & ~b | c".parse().unwrap();
+    /// assert_eq!(expr, (Expr::var("a") & !Expr::var("b")) | Expr::var("c"));
+    /// assert!("a b".parse::<Expr>().is_err());
+    /// ```
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut program = parse_str(input)?;
+        match (program.defs.is_empty(), program.exprs.len()) {
+            (true, 1) => Ok(program.exprs.remove(0)),
+            _ => Err(BlambdaError::new(
+                "expected a single expression without definitions",
+            )),
+        }
     }
 }
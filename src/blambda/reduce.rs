@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use crate::blambda::eval::{apply_kleene_binop, Kleene};
+use crate::blambda::syntax::{Expr, UnOp};
+
+/// Which redexes a small step contracts.
+///
+/// A redex is an operator whose operands are all values, or a ternary whose
+/// condition is `t` or `f`, or whose condition and branches are all values.
+/// Values include `u`, which operators treat as in Kleene's three-valued
+/// logic. Every strategy reaches the same value on a closed
+/// term, but since a ternary discards one of its branches, they can differ in
+/// how many steps it takes.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Strategy {
+    /// Normal order: the leftmost redex not contained in another redex. This
+    /// is the order in which `evaluate_expr` visits sub-expressions.
+    #[default]
+    LeftmostOutermost,
+    /// Applicative order: the leftmost redex containing no other redex, so
+    /// both branches of a ternary are reduced before one is chosen.
+    LeftmostInnermost,
+    /// Every redex not contained in another redex, all at once.
+    Parallel,
+}
+
+/// A redex and the expression it was contracted to.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Contraction {
+    pub redex: Expr,
+    pub reduct: Expr,
+}
+
+/// A single small step: the redexes it contracted, in left to right order,
+/// and the whole term afterwards.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Step {
+    pub contractions: Vec<Contraction>,
+    pub term: Expr,
+}
+
+fn as_value(expr: &Expr) -> Option<Kleene> {
+    match expr {
+        Expr::Value(v) => Some(Kleene::from(*v)),
+        Expr::Unknown => Some(Kleene::Unknown),
+        _ => None,
+    }
+}
+
+/// The expression a redex reduces to, or `None` if `expr` is not a redex.
+fn contract(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Value(_) | Expr::Unknown | Expr::Var(_) => None,
+        Expr::Unary { op, arg } => match op {
+            UnOp::Not => as_value(arg).map(|v| Expr::from(!v)),
+        },
+        Expr::Binary { op, arg1, arg2 } => Some(Expr::from(apply_kleene_binop(
+            *op,
+            as_value(arg1)?,
+            as_value(arg2)?,
+        ))),
+        Expr::And(args) => args
+            .iter()
+            .map(as_value)
+            .try_fold(Kleene::True, |acc, v| v.map(|v| acc.and(v)))
+            .map(Expr::from),
+        Expr::Or(args) => args
+            .iter()
+            .map(as_value)
+            .try_fold(Kleene::False, |acc, v| v.map(|v| acc.or(v)))
+            .map(Expr::from),
+        Expr::Ternary { cond, then, else_ } => match as_value(cond)?.known() {
+            Some(true) => Some(then.as_ref().clone()),
+            Some(false) => Some(else_.as_ref().clone()),
+            None => Some(Expr::from(
+                Kleene::Unknown.ite(as_value(then)?, as_value(else_)?),
+            )),
+        },
+    }
+}
+
+/// The operands of an expression, left to right.
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Value(_) | Expr::Unknown | Expr::Var(_) => Vec::new(),
+        Expr::Unary { arg, .. } => vec![arg],
+        Expr::Binary { arg1, arg2, .. } => vec![arg1, arg2],
+        Expr::And(args) | Expr::Or(args) => args.iter().collect(),
+        Expr::Ternary { cond, then, else_ } => vec![cond, then, else_],
+    }
+}
+
+/// Rebuilds an expression with new operands, in the order of `children`.
+fn with_children(expr: &Expr, children: Vec<Expr>) -> Expr {
+    let mut children = children.into_iter();
+    let mut next = || Arc::new(children.next().unwrap());
+    match expr {
+        Expr::Value(_) | Expr::Unknown | Expr::Var(_) => expr.clone(),
+        Expr::Unary { op, .. } => Expr::Unary {
+            op: *op,
+            arg: next(),
+        },
+        Expr::Binary { op, .. } => Expr::Binary {
+            op: *op,
+            arg1: next(),
+            arg2: next(),
+        },
+        Expr::And(_) => Expr::And(children.collect()),
+        Expr::Or(_) => Expr::Or(children.collect()),
+        Expr::Ternary { .. } => Expr::Ternary {
+            cond: next(),
+            then: next(),
+            else_: next(),
+        },
+    }
+}
+
+fn step_in(expr: &Expr, strategy: Strategy, contractions: &mut Vec<Contraction>) -> Option<Expr> {
+    let contract_here = |contractions: &mut Vec<Contraction>| {
+        let reduct = contract(expr)?;
+        contractions.push(Contraction {
+            redex: expr.clone(),
+            reduct: reduct.clone(),
+        });
+        Some(reduct)
+    };
+
+    match strategy {
+        Strategy::LeftmostOutermost => contract_here(contractions)
+            .or_else(|| step_leftmost_child(expr, strategy, contractions)),
+        Strategy::LeftmostInnermost => step_leftmost_child(expr, strategy, contractions)
+            .or_else(|| contract_here(contractions)),
+        Strategy::Parallel => contract_here(contractions).or_else(|| {
+            let children = children(expr);
+            let stepped: Vec<Option<Expr>> = children
+                .iter()
+                .map(|child| step_in(child, strategy, contractions))
+                .collect();
+            stepped.iter().any(Option::is_some).then(|| {
+                let children = children
+                    .into_iter()
+                    .zip(stepped)
+                    .map(|(child, stepped)| stepped.unwrap_or_else(|| child.clone()))
+                    .collect();
+                with_children(expr, children)
+            })
+        }),
+    }
+}
+
+fn step_leftmost_child(
+    expr: &Expr,
+    strategy: Strategy,
+    contractions: &mut Vec<Contraction>,
+) -> Option<Expr> {
+    let children = children(expr);
+    let (index, stepped) = children
+        .iter()
+        .enumerate()
+        .find_map(|(index, child)| Some((index, step_in(child, strategy, contractions)?)))?;
+    let mut children: Vec<Expr> = children.into_iter().cloned().collect();
+    children[index] = stepped;
+    Some(with_children(expr, children))
+}
+
+/// Takes one small step under `strategy`, recording the redexes contracted.
+/// Returns `None` once `expr` is a value, or if it is stuck on an unbound
+/// variable.
+pub fn step_traced(expr: &Expr, strategy: Strategy) -> Option<Step> {
+    let mut contractions = Vec::new();
+    let term = step_in(expr, strategy, &mut contractions)?;
+    Some(Step { contractions, term })
+}
+
+pub fn step_with(expr: &Expr, strategy: Strategy) -> Option<Expr> {
+    step_in(expr, strategy, &mut Vec::new())
+}
+
+/// Takes one small step in normal order.
+pub fn step(expr: &Expr) -> Option<Expr> {
+    step_with(expr, Strategy::default())
+}
+
+/// Steps `expr` under `strategy` until no step applies. For a closed term
+/// this is the value `evaluate_expr` gives, whichever strategy is used.
+pub fn reduce(expr: &Expr, strategy: Strategy) -> Expr {
+    let mut term = expr.clone();
+    while let Some(next) = step_with(&term, strategy) {
+        term = next;
+    }
+    term
+}
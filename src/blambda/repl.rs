@@ -0,0 +1,237 @@
+use pest::Parser;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::blambda::error::BlambdaError;
+use crate::blambda::eval::{evaluate_kleene_expr, truth_table, Env};
+use crate::blambda::format::format_expr;
+use crate::blambda::normalize::{free_vars, has_unknown, inline_definitions};
+use crate::blambda::parse::{parse_definition, parse_expr, BlambdaParser, Rule};
+use crate::blambda::serde_ast::serialize_definition_as;
+use crate::blambda::syntax::{Definition, Expr, Program};
+
+const HELP: &str = "\
+Enter expressions to evaluate them, or `name = expr` to define a name.
+  :ast [expr]    show the AST of an expression, or toggle showing it for every result
+  :fmt expr      format an expression without evaluating it
+  :table expr    print the truth table of an expression over its free variables
+  :load file     load the definitions and expressions of a file
+  :defs          list the definitions so far, in order
+  :help          show this message
+  :quit          leave the REPL";
+
+/// What the REPL should do after handling a line.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reply {
+    Text(String),
+    Quit,
+}
+
+/// A definition or expression of a line, in the order they appear.
+enum Item {
+    Def(Definition),
+    Expr(Expr),
+}
+
+/// `:table` refuses expressions with more free variables than this, rather
+/// than printing an enormous table.
+const MAX_TABLE_VARS: usize = 10;
+
+/// State of a REPL session: the definitions entered so far, which later
+/// lines may refer to, and display settings.
+#[derive(Debug, Clone)]
+pub struct Session {
+    defs: Vec<Definition>,
+    show_ast: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            defs: Vec::new(),
+            show_ast: false,
+        }
+    }
+
+    /// Handles one line of input, either a meta-command or blambda source.
+    pub fn handle_line(&mut self, line: &str) -> Result<Reply, BlambdaError> {
+        let line = line.trim();
+        let (command, argument) = match line.strip_prefix(':') {
+            Some(rest) => match rest.split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (rest, ""),
+            },
+            None => return self.run_source(line).map(Reply::Text),
+        };
+        let text = match (command, argument) {
+            ("q" | "quit", _) => return Ok(Reply::Quit),
+            ("h" | "help", _) => HELP.to_string(),
+            ("ast", "") => {
+                self.show_ast = !self.show_ast;
+                format!("AST display {}", if self.show_ast { "on" } else { "off" })
+            }
+            ("ast", source) => serde_yaml::to_string(&self.parse_expr(source)?)?,
+            ("fmt", source) => format_expr(&self.parse_expr(source)?),
+            ("table", source) => self.table(source)?,
+            ("load", path) => self.run_source(&std::fs::read_to_string(path)?)?,
+            ("defs", _) => self
+                .defs
+                .iter()
+                .map(|def| serialize_definition_as(def, Default::default()))
+                .collect::<Result<Vec<String>, BlambdaError>>()?
+                .join("\n"),
+            (command, _) => {
+                return Err(BlambdaError::new(format!(
+                    "unknown command `:{}`, see `:help`",
+                    command
+                )))
+            }
+        };
+        Ok(Reply::Text(text))
+    }
+
+    /// Parses the argument of a meta-command, which must be exactly one
+    /// expression.
+    fn parse_expr(&self, source: &str) -> Result<Expr, BlambdaError> {
+        source.parse()
+    }
+
+    /// Defines each definition in `source` and evaluates each expression, in
+    /// order, so that an expression sees only the definitions before it.
+    /// Redefining a name shadows the earlier definition for what follows
+    /// only, so `x = ~x` negates the old `x`.
+    fn run_source(&mut self, source: &str) -> Result<String, BlambdaError> {
+        if source.is_empty() {
+            return Ok(String::new());
+        }
+        // parse everything first, so that a malformed line defines nothing
+        let items = BlambdaParser::parse(Rule::program, source)?
+            .flat_map(|pair| pair.into_inner())
+            .filter_map(|pair| match pair.as_rule() {
+                Rule::definition => Some(parse_definition(pair).map(Item::Def)),
+                Rule::expr => Some(parse_expr(pair.into_inner()).map(Item::Expr)),
+                _ => None,
+            })
+            .collect::<Result<Vec<Item>, BlambdaError>>()?;
+        let mut lines = Vec::new();
+        for item in items {
+            match item {
+                Item::Def(def) => {
+                    lines.push(serialize_definition_as(&def, Default::default())?);
+                    self.defs.push(def);
+                }
+                Item::Expr(expr) => lines.push(self.evaluate(&expr)?),
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Substitutes the session's definitions into an expression.
+    fn resolve(&self, expr: &Expr) -> Expr {
+        let program = Program {
+            defs: self.defs.clone(),
+            exprs: vec![expr.clone()],
+        };
+        inline_definitions(&program).remove(0)
+    }
+
+    /// Evaluates an expression in three-valued logic, so that `u` is
+    /// accepted, but reports unbound variables whether or not it uses `u`.
+    fn evaluate(&self, expr: &Expr) -> Result<String, BlambdaError> {
+        let resolved = self.resolve(expr);
+        let vars = free_vars(&resolved);
+        if !vars.is_empty() {
+            return Err(BlambdaError::new(format!(
+                "cannot evaluate {}: unbound variables {}",
+                format_expr(expr),
+                vars.join(", ")
+            )));
+        }
+        let value: Expr = evaluate_kleene_expr(&resolved, &Env::new()).into();
+        let mut text = format!("{} => {}", format_expr(expr), format_expr(&value));
+        if self.show_ast {
+            text.push('\n');
+            text.push_str(serde_yaml::to_string(expr)?.trim_end());
+        }
+        Ok(text)
+    }
+
+    fn table(&self, source: &str) -> Result<String, BlambdaError> {
+        let expr = self.parse_expr(source)?;
+        let resolved = self.resolve(&expr);
+        if has_unknown(&resolved) {
+            return Err(BlambdaError::new(format!(
+                "cannot tabulate {}: `u` is neither t nor f, so it has no truth table",
+                format_expr(&expr)
+            )));
+        }
+        let vars = free_vars(&resolved);
+        if vars.len() > MAX_TABLE_VARS {
+            return Err(BlambdaError::new(format!(
+                "cannot tabulate {} free variables, at most {} are supported",
+                vars.len(),
+                MAX_TABLE_VARS
+            )));
+        }
+        let table = truth_table(&resolved)?;
+        let repr = |value: bool| if value { "t" } else { "f" };
+        let mut header: Vec<String> = table.vars.clone();
+        header.push("|".to_string());
+        header.push(format_expr(&expr));
+        let mut lines = vec![header.join(" ")];
+        for (inputs, output) in &table.rows {
+            let mut row: Vec<String> = inputs
+                .iter()
+                .zip(&table.vars)
+                .map(|(input, var)| format!("{:<width$}", repr(*input), width = var.len()))
+                .collect();
+            row.push("|".to_string());
+            row.push(repr(*output).to_string());
+            lines.push(row.join(" "));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Runs an interactive session on the terminal, with line editing and
+/// history persisted to `~/.blambda_history`.
+pub fn run() -> Result<(), BlambdaError> {
+    let mut editor = DefaultEditor::new()?;
+    let history =
+        std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".blambda_history"));
+    if let Some(history) = &history {
+        // a missing history file just means this is the first session
+        let _ = editor.load_history(history);
+    }
+
+    let mut session = Session::new();
+    loop {
+        match editor.readline("blambda> ") {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor.add_history_entry(line.as_str())?;
+                }
+                match session.handle_line(&line) {
+                    Ok(Reply::Text(text)) if text.is_empty() => (),
+                    Ok(Reply::Text(text)) => println!("{}", text),
+                    Ok(Reply::Quit) => break,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
@@ -1,44 +1,204 @@
-use serde::{ser::SerializeMap, Serialize};
+use std::sync::Arc;
 
-use crate::blambda::syntax::{BinOp, Expr, Program, UnOp};
+use serde::de::{self, MapAccess, Visitor};
+use serde::{ser::SerializeMap, Deserialize, Serialize};
+
+use crate::blambda::eval::Kleene;
+use crate::blambda::normalize::free_vars;
+use crate::blambda::parse::is_ident;
+use crate::blambda::syntax::{BinOp, Definition, Expr, Program, UnOp};
 
 use super::error::BlambdaError;
+use super::format::Notation;
 
-impl Serialize for Expr {
+/// Serializes a program or expression with each ternary in the legacy nested
+/// `condition`/`branch` binary shape, as emitted before `Expr::Ternary` existed.
+pub struct LegacyTernary<'a, T>(pub &'a T);
+
+/// An expression node together with the YAML shape its ternaries should take.
+struct Node<'a> {
+    expr: &'a Expr,
+    legacy_ternary: bool,
+}
+
+impl<'a> Node<'a> {
+    fn child(&self, expr: &'a Expr) -> Node<'a> {
+        Node {
+            expr,
+            legacy_ternary: self.legacy_ternary,
+        }
+    }
+}
+
+impl Serialize for Node<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        match self {
+        match self.expr {
             Expr::Value(v) => serializer.serialize_bool(*v),
+            Expr::Unknown => serializer.serialize_unit(),
+            Expr::Var(name) => serializer.serialize_str(name),
             Expr::Unary { op, arg } => {
                 let mut map = serializer.serialize_map(Some(2))?;
                 map.serialize_entry("op", op)?;
-                map.serialize_entry("arg", arg)?;
+                map.serialize_entry("arg", &self.child(arg))?;
                 map.end()
             }
             Expr::Binary { op, arg1, arg2 } => {
                 let mut map = serializer.serialize_map(Some(3))?;
                 map.serialize_entry("op", op)?;
-                map.serialize_entry("arg1", arg1)?;
-                map.serialize_entry("arg2", arg2)?;
+                map.serialize_entry("arg1", &self.child(arg1))?;
+                map.serialize_entry("arg2", &self.child(arg2))?;
+                map.end()
+            }
+            Expr::And(args) | Expr::Or(args) => {
+                let op = match self.expr {
+                    Expr::And(_) => BinOp::And,
+                    _ => BinOp::Or,
+                };
+                let args: Vec<Node> = args.iter().map(|arg| self.child(arg)).collect();
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("op", &op)?;
+                map.serialize_entry("args", &args)?;
+                map.end()
+            }
+            Expr::Ternary { cond, then, else_ } if self.legacy_ternary => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("op", "condition")?;
+                map.serialize_entry("arg1", &self.child(cond))?;
+                map.serialize_entry(
+                    "arg2",
+                    &LegacyBranch {
+                        then: self.child(then),
+                        else_: self.child(else_),
+                    },
+                )?;
+                map.end()
+            }
+            Expr::Ternary { cond, then, else_ } => {
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("op", "ternary")?;
+                map.serialize_entry("cond", &self.child(cond))?;
+                map.serialize_entry("then", &self.child(then))?;
+                map.serialize_entry("else", &self.child(else_))?;
                 map.end()
             }
         }
     }
 }
 
-impl Serialize for Program {
+struct LegacyBranch<'a> {
+    then: Node<'a>,
+    else_: Node<'a>,
+}
+
+impl Serialize for LegacyBranch<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_map(Some(1))?;
-        map.serialize_entry("exprs", &self.exprs)?;
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("op", "branch")?;
+        map.serialize_entry("arg1", &self.then)?;
+        map.serialize_entry("arg2", &self.else_)?;
         map.end()
     }
 }
 
+struct DefinitionNode<'a> {
+    name: &'a str,
+    body: Node<'a>,
+}
+
+impl Serialize for DefinitionNode<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("name", self.name)?;
+        map.serialize_entry("body", &self.body)?;
+        map.end()
+    }
+}
+
+/// Serializes a program's definitions (omitted when there are none, as in
+/// programs predating them) followed by its expressions.
+fn serialize_program_nodes<S>(
+    program: &Program,
+    legacy_ternary: bool,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let node = |expr| Node {
+        expr,
+        legacy_ternary,
+    };
+    let defs: Vec<DefinitionNode> = program
+        .defs
+        .iter()
+        .map(|def| DefinitionNode {
+            name: &def.name,
+            body: node(&def.body),
+        })
+        .collect();
+    let exprs: Vec<Node> = program.exprs.iter().map(node).collect();
+
+    let mut map = serializer.serialize_map(Some(if defs.is_empty() { 1 } else { 2 }))?;
+    if !defs.is_empty() {
+        map.serialize_entry("defs", &defs)?;
+    }
+    map.serialize_entry("exprs", &exprs)?;
+    map.end()
+}
+
+impl Serialize for Expr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Node {
+            expr: self,
+            legacy_ternary: false,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for LegacyTernary<'_, Expr> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Node {
+            expr: self.0,
+            legacy_ternary: true,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for Program {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serialize_program_nodes(self, false, serializer)
+    }
+}
+
+impl Serialize for LegacyTernary<'_, Program> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serialize_program_nodes(self.0, true, serializer)
+    }
+}
+
 impl Serialize for UnOp {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -58,68 +218,533 @@ impl Serialize for BinOp {
         match self {
             BinOp::Or => serializer.serialize_str("or"),
             BinOp::And => serializer.serialize_str("and"),
-            BinOp::Condition => serializer.serialize_str("condition"),
-            BinOp::Branch => serializer.serialize_str("branch"),
+            BinOp::Xor => serializer.serialize_str("xor"),
+            BinOp::Nand => serializer.serialize_str("nand"),
+            BinOp::Nor => serializer.serialize_str("nor"),
+            BinOp::Implies => serializer.serialize_str("implies"),
+            BinOp::Iff => serializer.serialize_str("iff"),
+        }
+    }
+}
+
+fn unop_from_name(name: &str) -> Option<UnOp> {
+    match name {
+        "not" => Some(UnOp::Not),
+        _ => None,
+    }
+}
+
+fn binop_from_name(name: &str) -> Option<BinOp> {
+    match name {
+        "or" => Some(BinOp::Or),
+        "and" => Some(BinOp::And),
+        "xor" => Some(BinOp::Xor),
+        "nand" => Some(BinOp::Nand),
+        "nor" => Some(BinOp::Nor),
+        "implies" => Some(BinOp::Implies),
+        "iff" => Some(BinOp::Iff),
+        _ => None,
+    }
+}
+
+impl<'de> Deserialize<'de> for UnOp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        unop_from_name(&name).ok_or_else(|| de::Error::unknown_variant(&name, &["not"]))
+    }
+}
+
+impl<'de> Deserialize<'de> for BinOp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        binop_from_name(&name).ok_or_else(|| {
+            de::Error::unknown_variant(
+                &name,
+                &["or", "and", "xor", "nand", "nor", "implies", "iff"],
+            )
+        })
+    }
+}
+
+/// What a variable or definition name must be, for deserialization errors.
+const NAME: &str = "a variable name, which is not a keyword";
+
+/// An AST node as written in YAML/JSON, before its `op` has been checked
+/// against the fields that accompany it.
+enum RawExpr {
+    Value(bool),
+    Unknown,
+    Var(String),
+    Node(Box<RawNode>),
+}
+
+// operands use `present` since a `null` operand is `u`, not a missing one
+#[derive(serde_derive::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawNode {
+    op: String,
+    #[serde(default, deserialize_with = "present")]
+    arg: Option<RawExpr>,
+    #[serde(default, deserialize_with = "present")]
+    arg1: Option<RawExpr>,
+    #[serde(default, deserialize_with = "present")]
+    arg2: Option<RawExpr>,
+    args: Option<Vec<RawExpr>>,
+    #[serde(default, deserialize_with = "present")]
+    cond: Option<RawExpr>,
+    #[serde(default, deserialize_with = "present")]
+    then: Option<RawExpr>,
+    #[serde(rename = "else", default, deserialize_with = "present")]
+    else_: Option<RawExpr>,
+}
+
+impl RawNode {
+    /// Rejects any operand field that is not among those `op` takes.
+    fn expect_fields<E: de::Error>(&self, allowed: &[&str]) -> Result<(), E> {
+        let fields = [
+            ("arg", self.arg.is_some()),
+            ("arg1", self.arg1.is_some()),
+            ("arg2", self.arg2.is_some()),
+            ("args", self.args.is_some()),
+            ("cond", self.cond.is_some()),
+            ("then", self.then.is_some()),
+            ("else", self.else_.is_some()),
+        ];
+        match fields
+            .iter()
+            .find(|(name, present)| *present && !allowed.contains(name))
+        {
+            Some((name, _)) => Err(E::custom(format!(
+                "`{}` node does not take `{}`",
+                self.op, name
+            ))),
+            None => Ok(()),
         }
     }
 }
 
+fn present<'de, D>(deserializer: D) -> Result<Option<RawExpr>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    RawExpr::deserialize(deserializer).map(Some)
+}
+
+impl<'de> Deserialize<'de> for RawExpr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct RawExprVisitor;
+
+        impl<'de> Visitor<'de> for RawExprVisitor {
+            type Value = RawExpr;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a boolean, null, a variable name or a map with an `op` field")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(RawExpr::Value(v))
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(RawExpr::Unknown)
+            }
+
+            fn visit_none<E>(self) -> Result<Self::Value, E> {
+                Ok(RawExpr::Unknown)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                // a name the source syntax could not spell would not survive formatting
+                if !is_ident(v) {
+                    return Err(E::invalid_value(de::Unexpected::Str(v), &NAME));
+                }
+                Ok(RawExpr::Var(v.to_string()))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let node = RawNode::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(RawExpr::Node(Box::new(node)))
+            }
+        }
+
+        deserializer.deserialize_any(RawExprVisitor)
+    }
+}
+
+fn required<E: de::Error>(field: Option<RawExpr>, op: &str, name: &str) -> Result<Arc<Expr>, E> {
+    match field {
+        Some(raw) => Ok(Arc::new(raw.into_expr()?)),
+        None => Err(E::custom(format!(
+            "`{}` node is missing field `{}`",
+            op, name
+        ))),
+    }
+}
+
+impl RawExpr {
+    fn into_expr<E: de::Error>(self) -> Result<Expr, E> {
+        let node = match self {
+            RawExpr::Value(v) => return Ok(Expr::Value(v)),
+            RawExpr::Unknown => return Ok(Expr::Unknown),
+            RawExpr::Var(name) => return Ok(Expr::Var(name)),
+            RawExpr::Node(node) => *node,
+        };
+        let op = node.op.as_str();
+        if let Some(op) = unop_from_name(op) {
+            node.expect_fields(&["arg"])?;
+            return Ok(Expr::Unary {
+                op,
+                arg: required(node.arg, &node.op, "arg")?,
+            });
+        }
+        if let Some(binop) = binop_from_name(op) {
+            if let (BinOp::And | BinOp::Or, Some(_)) = (binop, &node.args) {
+                node.expect_fields(&["args"])?;
+                let args = node
+                    .args
+                    .into_iter()
+                    .flatten()
+                    .map(RawExpr::into_expr)
+                    .collect::<Result<_, E>>()?;
+                return Ok(match binop {
+                    BinOp::And => Expr::And(args),
+                    _ => Expr::Or(args),
+                });
+            }
+            node.expect_fields(&["arg1", "arg2"])?;
+            return Ok(Expr::Binary {
+                op: binop,
+                arg1: required(node.arg1, op, "arg1")?,
+                arg2: required(node.arg2, op, "arg2")?,
+            });
+        }
+        match op {
+            "ternary" => {
+                node.expect_fields(&["cond", "then", "else"])?;
+                Ok(Expr::Ternary {
+                    cond: required(node.cond, op, "cond")?,
+                    then: required(node.then, op, "then")?,
+                    else_: required(node.else_, op, "else")?,
+                })
+            }
+            // legacy shape: `condition` whose `arg2` is a `branch` node
+            "condition" => {
+                node.expect_fields(&["arg1", "arg2"])?;
+                match node.arg2 {
+                    Some(RawExpr::Node(branch)) if branch.op == "branch" => {
+                        branch.expect_fields(&["arg1", "arg2"])?;
+                        Ok(Expr::Ternary {
+                            cond: required(node.arg1, op, "arg1")?,
+                            then: required(branch.arg1, "branch", "arg1")?,
+                            else_: required(branch.arg2, "branch", "arg2")?,
+                        })
+                    }
+                    _ => Err(E::custom(
+                        "`condition` node must have a `branch` node as `arg2`",
+                    )),
+                }
+            }
+            "branch" => Err(E::custom("`branch` node outside of a `condition` node")),
+            _ => Err(E::custom(format!("unknown op `{}`", op))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        RawExpr::deserialize(deserializer)?.into_expr()
+    }
+}
+
+impl<'de> Deserialize<'de> for Program {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde_derive::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct RawDefinition {
+            name: String,
+            body: Expr,
+        }
+
+        #[derive(serde_derive::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct RawProgram {
+            #[serde(default)]
+            defs: Vec<RawDefinition>,
+            exprs: Vec<Expr>,
+        }
+
+        let program = RawProgram::deserialize(deserializer)?;
+        if let Some(def) = program.defs.iter().find(|def| !is_ident(&def.name)) {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(&def.name),
+                &NAME,
+            ));
+        }
+        // as in source, a definition only applies to the definitions after it
+        for (index, def) in program.defs.iter().enumerate() {
+            let later = &program.defs[index..];
+            if let Some(var) = free_vars(&def.body)
+                .into_iter()
+                .find(|var| later.iter().any(|def| def.name == *var))
+            {
+                return Err(de::Error::custom(format!(
+                    "`{}` is used before its definition; definitions only apply to what follows them",
+                    var
+                )));
+            }
+        }
+        Ok(Program {
+            defs: program
+                .defs
+                .into_iter()
+                .map(|def| Definition {
+                    name: def.name,
+                    body: def.body,
+                })
+                .collect(),
+            exprs: program.exprs,
+        })
+    }
+}
+
 trait BlambdaRepr {
-    fn blambda_repr(&self) -> String;
+    fn blambda_repr(&self, notation: Notation) -> String;
 }
 
 impl BlambdaRepr for bool {
-    fn blambda_repr(&self) -> String {
-        format!("{}", if *self { "t" } else { "f" })
+    fn blambda_repr(&self, notation: Notation) -> String {
+        match (notation, self) {
+            (Notation::Ascii, true) => "t",
+            (Notation::Ascii, false) => "f",
+            (Notation::Unicode, true) => "⊤",
+            (Notation::Unicode, false) => "⊥",
+            (Notation::Keyword, true) => "true",
+            (Notation::Keyword, false) => "false",
+        }
+        .to_string()
+    }
+}
+
+impl BlambdaRepr for Kleene {
+    fn blambda_repr(&self, notation: Notation) -> String {
+        match (notation, self.known()) {
+            (_, Some(v)) => v.blambda_repr(notation),
+            (Notation::Keyword, None) => "unknown".to_string(),
+            (_, None) => "u".to_string(),
+        }
     }
 }
 
 impl BlambdaRepr for UnOp {
-    fn blambda_repr(&self) -> String {
-        match self {
-            UnOp::Not => "~".to_string(),
+    fn blambda_repr(&self, notation: Notation) -> String {
+        match (notation, self) {
+            (Notation::Ascii, UnOp::Not) => "~",
+            (Notation::Unicode, UnOp::Not) => "¬",
+            (Notation::Keyword, UnOp::Not) => "not",
         }
+        .to_string()
     }
 }
 
 impl BlambdaRepr for BinOp {
-    fn blambda_repr(&self) -> String {
-        match self {
-            BinOp::Or => "|".to_string(),
-            BinOp::And => "&".to_string(),
-            BinOp::Condition => "?".to_string(),
-            BinOp::Branch => ":".to_string(),
+    fn blambda_repr(&self, notation: Notation) -> String {
+        match (notation, self) {
+            (Notation::Ascii, BinOp::Or) => "|",
+            (Notation::Ascii, BinOp::And) => "&",
+            (Notation::Ascii, BinOp::Xor) => "^",
+            (Notation::Ascii, BinOp::Nand) => "!&",
+            (Notation::Ascii, BinOp::Nor) => "!|",
+            (Notation::Ascii, BinOp::Implies) => "->",
+            (Notation::Ascii, BinOp::Iff) => "<->",
+            (Notation::Unicode, BinOp::Or) => "∨",
+            (Notation::Unicode, BinOp::And) => "∧",
+            (Notation::Unicode, BinOp::Xor) => "⊕",
+            (Notation::Unicode, BinOp::Nand) => "↑",
+            (Notation::Unicode, BinOp::Nor) => "↓",
+            (Notation::Unicode, BinOp::Implies) => "→",
+            (Notation::Unicode, BinOp::Iff) => "↔",
+            (Notation::Keyword, BinOp::Or) => "or",
+            (Notation::Keyword, BinOp::And) => "and",
+            (Notation::Keyword, BinOp::Xor) => "xor",
+            (Notation::Keyword, BinOp::Nand) => "nand",
+            (Notation::Keyword, BinOp::Nor) => "nor",
+            (Notation::Keyword, BinOp::Implies) => "implies",
+            (Notation::Keyword, BinOp::Iff) => "iff",
         }
+        .to_string()
     }
 }
 
 pub fn serialize_expr(expr: &Expr) -> Result<String, BlambdaError> {
+    serialize_expr_as(expr, Notation::Ascii)
+}
+
+pub fn serialize_expr_as(expr: &Expr, notation: Notation) -> Result<String, BlambdaError> {
     match expr {
-        Expr::Value(v) => Ok(v.blambda_repr()),
+        Expr::Value(v) => Ok(v.blambda_repr(notation)),
+        Expr::Unknown => Ok(Kleene::Unknown.blambda_repr(notation)),
+        Expr::Var(name) => Ok(name.clone()),
         Expr::Unary { op, arg } => {
-            let arg = serialize_expr(arg)?;
-            Ok(format!("({} {})", op.blambda_repr(), arg))
+            let arg = serialize_expr_as(arg, notation)?;
+            Ok(format!("({} {})", op.blambda_repr(notation), arg))
         }
         Expr::Binary { op, arg1, arg2 } => {
-            let arg1 = serialize_expr(arg1)?;
-            let arg2 = serialize_expr(arg2)?;
-            Ok(format!("({} {} {})", arg1, op.blambda_repr(), arg2))
+            let arg1 = serialize_expr_as(arg1, notation)?;
+            let arg2 = serialize_expr_as(arg2, notation)?;
+            Ok(format!("({} {} {})", arg1, op.blambda_repr(notation), arg2))
+        }
+        Expr::And(args) | Expr::Or(args) => {
+            let (op, identity) = match expr {
+                Expr::And(_) => (BinOp::And, true),
+                _ => (BinOp::Or, false),
+            };
+            match args.as_slice() {
+                [] => Ok(identity.blambda_repr(notation)),
+                [arg] => serialize_expr_as(arg, notation),
+                args => {
+                    let args = args
+                        .iter()
+                        .map(|arg| serialize_expr_as(arg, notation))
+                        .collect::<Result<Vec<String>, BlambdaError>>()?;
+                    let separator = format!(" {} ", op.blambda_repr(notation));
+                    Ok(format!("({})", args.join(&separator)))
+                }
+            }
+        }
+        Expr::Ternary { cond, then, else_ } => {
+            let cond = serialize_expr_as(cond, notation)?;
+            let then = serialize_expr_as(then, notation)?;
+            let else_ = serialize_expr_as(else_, notation)?;
+            Ok(format!("({} ? {} : {})", cond, then, else_))
         }
     }
 }
 
 pub fn serialize_program(program: &Program) -> Result<String, BlambdaError> {
+    serialize_program_as(program, Notation::Ascii)
+}
+
+pub fn serialize_definition_as(
+    def: &Definition,
+    notation: Notation,
+) -> Result<String, BlambdaError> {
+    Ok(format!(
+        "{} = {}",
+        def.name,
+        serialize_expr_as(&def.body, notation)?
+    ))
+}
+
+pub fn serialize_program_as(program: &Program, notation: Notation) -> Result<String, BlambdaError> {
     let mut exprs = Vec::new();
+    for def in &program.defs {
+        exprs.push(serialize_definition_as(def, notation)?);
+    }
     for expr in &program.exprs {
-        exprs.push(serialize_expr(expr)?);
+        exprs.push(serialize_expr_as(expr, notation)?);
     }
-    Ok(format!(
-        "{}",
-        &program
-            .exprs
-            .iter()
-            .flat_map(|expr| serialize_expr(expr))
-            .collect::<Vec<String>>()
-            .join(" ")
-    ))
+    Ok(exprs.join(" "))
+}
+
+trait SexprRepr {
+    fn sexpr_repr(&self) -> String;
+}
+
+impl SexprRepr for bool {
+    fn sexpr_repr(&self) -> String {
+        self.blambda_repr(Notation::Ascii)
+    }
+}
+
+impl SexprRepr for UnOp {
+    fn sexpr_repr(&self) -> String {
+        match self {
+            UnOp::Not => "not".to_string(),
+        }
+    }
+}
+
+impl SexprRepr for BinOp {
+    fn sexpr_repr(&self) -> String {
+        match self {
+            BinOp::Or => "or",
+            BinOp::And => "and",
+            BinOp::Xor => "xor",
+            BinOp::Nand => "nand",
+            BinOp::Nor => "nor",
+            BinOp::Implies => "implies",
+            BinOp::Iff => "iff",
+        }
+        .to_string()
+    }
+}
+
+/// Serializes an expression as an S-expression, e.g. `(or t (not f))`.
+pub fn serialize_sexpr(expr: &Expr) -> Result<String, BlambdaError> {
+    match expr {
+        Expr::Value(v) => Ok(v.sexpr_repr()),
+        Expr::Unknown => Ok(Kleene::Unknown.blambda_repr(Notation::Ascii)),
+        Expr::Var(name) => Ok(name.clone()),
+        Expr::Unary { op, arg } => Ok(format!("({} {})", op.sexpr_repr(), serialize_sexpr(arg)?)),
+        Expr::Binary { op, arg1, arg2 } => Ok(format!(
+            "({} {} {})",
+            op.sexpr_repr(),
+            serialize_sexpr(arg1)?,
+            serialize_sexpr(arg2)?
+        )),
+        Expr::And(args) | Expr::Or(args) => {
+            let mut parts = vec![match expr {
+                Expr::And(_) => BinOp::And.sexpr_repr(),
+                _ => BinOp::Or.sexpr_repr(),
+            }];
+            for arg in args {
+                parts.push(serialize_sexpr(arg)?);
+            }
+            Ok(format!("({})", parts.join(" ")))
+        }
+        Expr::Ternary { cond, then, else_ } => Ok(format!(
+            "(ternary {} {} {})",
+            serialize_sexpr(cond)?,
+            serialize_sexpr(then)?,
+            serialize_sexpr(else_)?
+        )),
+    }
+}
+
+/// Serializes a program as one S-expression per line, starting with a
+/// `(define name body)` form for each definition.
+pub fn serialize_program_sexpr(program: &Program) -> Result<String, BlambdaError> {
+    let mut exprs = Vec::new();
+    for def in &program.defs {
+        exprs.push(format!(
+            "(define {} {})",
+            def.name,
+            serialize_sexpr(&def.body)?
+        ));
+    }
+    for expr in &program.exprs {
+        exprs.push(serialize_sexpr(expr)?);
+    }
+    Ok(exprs.join("\n"))
 }
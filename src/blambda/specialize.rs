@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blambda::eval::{apply_binop, Env};
+use crate::blambda::syntax::{BinOp, Definition, Expr, Fold, Program, UnOp};
+
+/// Folds constants bottom-up, replacing each variable whose value is known.
+struct Specializer<'a> {
+    known: &'a Env,
+}
+
+fn as_value(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Value(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn not(expr: Expr) -> Expr {
+    match expr {
+        Expr::Value(v) => Expr::Value(!v),
+        Expr::Unary { op: UnOp::Not, arg } => Arc::unwrap_or_clone(arg),
+        expr => !expr,
+    }
+}
+
+/// Simplifies `op` applied to a constant and an expression, in either order.
+fn with_constant(op: BinOp, value: bool, other: Expr, value_first: bool) -> Expr {
+    match (op, value, value_first) {
+        (BinOp::And, true, _) | (BinOp::Or, false, _) | (BinOp::Xor, false, _) => other,
+        (BinOp::And, false, _) | (BinOp::Or, true, _) => Expr::Value(value),
+        (BinOp::Xor, true, _) | (BinOp::Nand, true, _) | (BinOp::Nor, false, _) => not(other),
+        (BinOp::Nand, false, _) => Expr::Value(true),
+        (BinOp::Nor, true, _) => Expr::Value(false),
+        (BinOp::Iff, true, _) => other,
+        (BinOp::Iff, false, _) => not(other),
+        // `f -> x` and `x -> t` always hold
+        (BinOp::Implies, false, true) | (BinOp::Implies, true, false) => Expr::Value(true),
+        (BinOp::Implies, true, true) => other,
+        (BinOp::Implies, false, false) => not(other),
+    }
+}
+
+/// Folds the operands of an n-ary `&` (`identity` true) or `|` (`identity`
+/// false), dropping identities and stopping at an annihilator.
+fn fold_chain(args: Vec<Expr>, identity: bool) -> Result<Vec<Expr>, bool> {
+    let mut residual = Vec::new();
+    for arg in args {
+        match as_value(&arg) {
+            Some(v) if v == identity => (),
+            Some(v) => return Err(v),
+            None => residual.push(arg),
+        }
+    }
+    Ok(residual)
+}
+
+impl Fold for Specializer<'_> {
+    fn fold_var(&mut self, name: &str) -> Expr {
+        match self.known.get(name) {
+            Some(v) => Expr::Value(*v),
+            None => Expr::var(name),
+        }
+    }
+
+    fn fold_unary(&mut self, op: UnOp, arg: Expr) -> Expr {
+        match op {
+            UnOp::Not => not(arg),
+        }
+    }
+
+    fn fold_binary(&mut self, op: BinOp, arg1: Expr, arg2: Expr) -> Expr {
+        match (as_value(&arg1), as_value(&arg2)) {
+            (Some(v1), Some(v2)) => Expr::Value(apply_binop(op, v1, v2)),
+            (Some(v1), None) => with_constant(op, v1, arg2, true),
+            (None, Some(v2)) => with_constant(op, v2, arg1, false),
+            (None, None) => Expr::binary(op, arg1, arg2),
+        }
+    }
+
+    fn fold_and(&mut self, args: Vec<Expr>) -> Expr {
+        match fold_chain(args, true) {
+            Err(v) => Expr::Value(v),
+            Ok(mut args) if args.len() <= 1 => args.pop().unwrap_or(Expr::Value(true)),
+            Ok(args) => Expr::And(args),
+        }
+    }
+
+    fn fold_or(&mut self, args: Vec<Expr>) -> Expr {
+        match fold_chain(args, false) {
+            Err(v) => Expr::Value(v),
+            Ok(mut args) if args.len() <= 1 => args.pop().unwrap_or(Expr::Value(false)),
+            Ok(args) => Expr::Or(args),
+        }
+    }
+
+    fn fold_ternary(&mut self, cond: Expr, then: Expr, else_: Expr) -> Expr {
+        match (as_value(&cond), as_value(&then), as_value(&else_)) {
+            (Some(true), _, _) => then,
+            (Some(false), _, _) => else_,
+            _ if then == else_ => then,
+            (None, Some(true), Some(false)) => cond,
+            (None, Some(false), Some(true)) => not(cond),
+            _ => Expr::ite(cond, then, else_),
+        }
+    }
+}
+
+/// Partially evaluates an expression, substituting the variables bound in
+/// `known` and folding every operator whose result they determine.
+pub fn specialize_expr(expr: &Expr, known: &Env) -> Expr {
+    Specializer { known }.fold(expr)
+}
+
+/// Partially evaluates a program under a partial assignment to its free
+/// variables. Definitions whose bodies fold to a constant are substituted
+/// into their uses and dropped, so the residual program only defines what
+/// still depends on unknown variables. If that leaves nothing at all, the
+/// last definition is kept with its value, since a program is never empty.
+pub fn specialize_program(program: &Program, env: &Env) -> Program {
+    let mut known: HashMap<String, bool> = env.clone();
+    let mut defs = Vec::new();
+    let mut last_folded = None;
+    for def in &program.defs {
+        let body = specialize_expr(&def.body, &known);
+        // a definition shadows any assignment to a variable of the same name
+        match as_value(&body) {
+            Some(v) => {
+                known.insert(def.name.clone(), v);
+                last_folded = Some(Definition {
+                    name: def.name.clone(),
+                    body,
+                });
+            }
+            None => {
+                known.remove(&def.name);
+                defs.push(Definition {
+                    name: def.name.clone(),
+                    body,
+                });
+            }
+        }
+    }
+    if defs.is_empty() && program.exprs.is_empty() {
+        defs.extend(last_folded);
+    }
+    Program {
+        defs,
+        exprs: program
+            .exprs
+            .iter()
+            .map(|expr| specialize_expr(expr, &known))
+            .collect(),
+    }
+}
@@ -1,9 +1,14 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
 use std::sync::Arc;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     // Terminal values
     Value(bool),
+    // The unknown value `u` of three-valued logic
+    Unknown,
+    // Variables, bound by a definition or left free
+    Var(String),
     // Unary operator(s)
     Unary {
         op: UnOp,
@@ -15,22 +20,337 @@ pub enum Expr {
         arg1: Arc<Expr>,
         arg2: Arc<Expr>,
     },
+    // N-ary conjunction and disjunction, produced by `normalize`
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    // Ternary operator
+    Ternary {
+        cond: Arc<Expr>,
+        then: Arc<Expr>,
+        else_: Arc<Expr>,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub body: Expr,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Program {
+    pub defs: Vec<Definition>,
     pub exprs: Vec<Expr>,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum UnOp {
     Not,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum BinOp {
     Or,
     And,
-    Condition,
-    Branch,
+    Xor,
+    Nand,
+    Nor,
+    Implies,
+    Iff,
+}
+
+impl Expr {
+    pub fn var(name: impl Into<String>) -> Self {
+        Expr::Var(name.into())
+    }
+
+    pub fn binary(op: BinOp, arg1: Expr, arg2: Expr) -> Self {
+        Expr::Binary {
+            op,
+            arg1: Arc::new(arg1),
+            arg2: Arc::new(arg2),
+        }
+    }
+
+    /// `cond ? then : else_`
+    pub fn ite(cond: Expr, then: Expr, else_: Expr) -> Self {
+        Expr::Ternary {
+            cond: Arc::new(cond),
+            then: Arc::new(then),
+            else_: Arc::new(else_),
+        }
+    }
+
+    pub fn nand(self, other: Expr) -> Self {
+        Expr::binary(BinOp::Nand, self, other)
+    }
+
+    pub fn nor(self, other: Expr) -> Self {
+        Expr::binary(BinOp::Nor, self, other)
+    }
+
+    pub fn implies(self, other: Expr) -> Self {
+        Expr::binary(BinOp::Implies, self, other)
+    }
+
+    pub fn iff(self, other: Expr) -> Self {
+        Expr::binary(BinOp::Iff, self, other)
+    }
+}
+
+impl From<bool> for Expr {
+    fn from(value: bool) -> Self {
+        Expr::Value(value)
+    }
+}
+
+impl BitAnd for Expr {
+    type Output = Expr;
+
+    fn bitand(self, other: Expr) -> Expr {
+        Expr::binary(BinOp::And, self, other)
+    }
+}
+
+impl BitOr for Expr {
+    type Output = Expr;
+
+    fn bitor(self, other: Expr) -> Expr {
+        Expr::binary(BinOp::Or, self, other)
+    }
+}
+
+impl BitXor for Expr {
+    type Output = Expr;
+
+    fn bitxor(self, other: Expr) -> Expr {
+        Expr::binary(BinOp::Xor, self, other)
+    }
+}
+
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Unary {
+            op: UnOp::Not,
+            arg: Arc::new(self),
+        }
+    }
+}
+
+/// A read-only pass over an expression. Each `visit_*` method is called once
+/// per node of its kind, parents before operands and operands left to right,
+/// and does nothing unless overridden.
+pub trait Visit {
+    fn visit_value(&mut self, _value: bool) {}
+
+    fn visit_unknown(&mut self) {}
+
+    fn visit_var(&mut self, _name: &str) {}
+
+    fn visit_unary(&mut self, _op: UnOp, _arg: &Expr) {}
+
+    fn visit_binary(&mut self, _op: BinOp, _arg1: &Expr, _arg2: &Expr) {}
+
+    fn visit_and(&mut self, _args: &[Expr]) {}
+
+    fn visit_or(&mut self, _args: &[Expr]) {}
+
+    fn visit_ternary(&mut self, _cond: &Expr, _then: &Expr, _else_: &Expr) {}
+
+    /// Called for every node before the method for its kind, which it
+    /// dispatches to unless overridden.
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Value(v) => self.visit_value(*v),
+            Expr::Unknown => self.visit_unknown(),
+            Expr::Var(name) => self.visit_var(name),
+            Expr::Unary { op, arg } => self.visit_unary(*op, arg),
+            Expr::Binary { op, arg1, arg2 } => self.visit_binary(*op, arg1, arg2),
+            Expr::And(args) => self.visit_and(args),
+            Expr::Or(args) => self.visit_or(args),
+            Expr::Ternary { cond, then, else_ } => self.visit_ternary(cond, then, else_),
+        }
+    }
+
+    /// Visits `expr` and all its sub-expressions, using a stack rather than
+    /// recursion so that deep expressions cannot overflow.
+    fn walk(&mut self, expr: &Expr) {
+        let mut stack = vec![expr];
+        while let Some(expr) = stack.pop() {
+            self.visit_expr(expr);
+            match expr {
+                Expr::Value(_) | Expr::Unknown | Expr::Var(_) => (),
+                Expr::Unary { arg, .. } => stack.push(arg),
+                Expr::Binary { arg1, arg2, .. } => stack.extend([&**arg2, &**arg1]),
+                Expr::And(args) | Expr::Or(args) => stack.extend(args.iter().rev()),
+                Expr::Ternary { cond, then, else_ } => stack.extend([&**else_, &**then, &**cond]),
+            }
+        }
+    }
+}
+
+/// An in-place rewrite of an expression. `visit_expr_mut` is called for every
+/// node, parents before operands, so operands it puts in place are visited
+/// too. Operands shared with other expressions are copied before they are
+/// visited.
+pub trait VisitMut {
+    fn visit_value_mut(&mut self, _value: &mut bool) {}
+
+    /// Receives the `u` node itself, which has no fields, so that it can be
+    /// replaced.
+    fn visit_unknown_mut(&mut self, _expr: &mut Expr) {}
+
+    fn visit_var_mut(&mut self, _name: &mut String) {}
+
+    fn visit_unary_mut(&mut self, _op: &mut UnOp, _arg: &mut Expr) {}
+
+    fn visit_binary_mut(&mut self, _op: &mut BinOp, _arg1: &mut Expr, _arg2: &mut Expr) {}
+
+    fn visit_and_mut(&mut self, _args: &mut Vec<Expr>) {}
+
+    fn visit_or_mut(&mut self, _args: &mut Vec<Expr>) {}
+
+    fn visit_ternary_mut(&mut self, _cond: &mut Expr, _then: &mut Expr, _else_: &mut Expr) {}
+
+    /// Called for every node, dispatching to the method for its kind unless
+    /// overridden. Overrides may replace the node outright.
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Value(v) => self.visit_value_mut(v),
+            Expr::Unknown => self.visit_unknown_mut(expr),
+            Expr::Var(name) => self.visit_var_mut(name),
+            Expr::Unary { op, arg } => self.visit_unary_mut(op, Arc::make_mut(arg)),
+            Expr::Binary { op, arg1, arg2 } => {
+                self.visit_binary_mut(op, Arc::make_mut(arg1), Arc::make_mut(arg2))
+            }
+            Expr::And(args) => self.visit_and_mut(args),
+            Expr::Or(args) => self.visit_or_mut(args),
+            Expr::Ternary { cond, then, else_ } => self.visit_ternary_mut(
+                Arc::make_mut(cond),
+                Arc::make_mut(then),
+                Arc::make_mut(else_),
+            ),
+        }
+    }
+
+    /// Visits `expr` and all its sub-expressions without recursion.
+    fn walk_mut(&mut self, expr: &mut Expr) {
+        let mut stack = vec![expr];
+        while let Some(expr) = stack.pop() {
+            self.visit_expr_mut(expr);
+            match expr {
+                Expr::Value(_) | Expr::Unknown | Expr::Var(_) => (),
+                Expr::Unary { arg, .. } => stack.push(Arc::make_mut(arg)),
+                Expr::Binary { arg1, arg2, .. } => {
+                    stack.extend([Arc::make_mut(arg2), Arc::make_mut(arg1)])
+                }
+                Expr::And(args) | Expr::Or(args) => stack.extend(args.iter_mut().rev()),
+                Expr::Ternary { cond, then, else_ } => stack.extend([
+                    Arc::make_mut(else_),
+                    Arc::make_mut(then),
+                    Arc::make_mut(cond),
+                ]),
+            }
+        }
+    }
+}
+
+/// A bottom-up rebuild of an expression. Each `fold_*` method receives its
+/// node's operands already folded, and by default rebuilds the node
+/// unchanged.
+pub trait Fold {
+    fn fold_value(&mut self, value: bool) -> Expr {
+        Expr::Value(value)
+    }
+
+    fn fold_unknown(&mut self) -> Expr {
+        Expr::Unknown
+    }
+
+    fn fold_var(&mut self, name: &str) -> Expr {
+        Expr::Var(name.to_string())
+    }
+
+    fn fold_unary(&mut self, op: UnOp, arg: Expr) -> Expr {
+        Expr::Unary {
+            op,
+            arg: Arc::new(arg),
+        }
+    }
+
+    fn fold_binary(&mut self, op: BinOp, arg1: Expr, arg2: Expr) -> Expr {
+        Expr::binary(op, arg1, arg2)
+    }
+
+    fn fold_and(&mut self, args: Vec<Expr>) -> Expr {
+        Expr::And(args)
+    }
+
+    fn fold_or(&mut self, args: Vec<Expr>) -> Expr {
+        Expr::Or(args)
+    }
+
+    fn fold_ternary(&mut self, cond: Expr, then: Expr, else_: Expr) -> Expr {
+        Expr::ite(cond, then, else_)
+    }
+
+    /// Folds `expr`, operands before the nodes that use them and left to
+    /// right, using a stack rather than recursion.
+    fn fold(&mut self, expr: &Expr) -> Expr {
+        // each node is pushed twice: to schedule its operands, then to fold
+        // them once they are on `done`
+        let mut stack = vec![(expr, false)];
+        let mut done: Vec<Expr> = Vec::new();
+        while let Some((expr, ready)) = stack.pop() {
+            if !ready {
+                stack.push((expr, true));
+                match expr {
+                    Expr::Value(_) | Expr::Unknown | Expr::Var(_) => (),
+                    Expr::Unary { arg, .. } => stack.push((arg, false)),
+                    Expr::Binary { arg1, arg2, .. } => {
+                        stack.extend([(&**arg2, false), (&**arg1, false)])
+                    }
+                    Expr::And(args) | Expr::Or(args) => {
+                        stack.extend(args.iter().rev().map(|arg| (arg, false)))
+                    }
+                    Expr::Ternary { cond, then, else_ } => {
+                        stack.extend([(&**else_, false), (&**then, false), (&**cond, false)])
+                    }
+                }
+                continue;
+            }
+            let folded = match expr {
+                Expr::Value(v) => self.fold_value(*v),
+                Expr::Unknown => self.fold_unknown(),
+                Expr::Var(name) => self.fold_var(name),
+                Expr::Unary { op, .. } => {
+                    let arg = done.pop().unwrap();
+                    self.fold_unary(*op, arg)
+                }
+                Expr::Binary { op, .. } => {
+                    let arg2 = done.pop().unwrap();
+                    let arg1 = done.pop().unwrap();
+                    self.fold_binary(*op, arg1, arg2)
+                }
+                Expr::And(args) => {
+                    let args = done.split_off(done.len() - args.len());
+                    self.fold_and(args)
+                }
+                Expr::Or(args) => {
+                    let args = done.split_off(done.len() - args.len());
+                    self.fold_or(args)
+                }
+                Expr::Ternary { .. } => {
+                    let else_ = done.pop().unwrap();
+                    let then = done.pop().unwrap();
+                    let cond = done.pop().unwrap();
+                    self.fold_ternary(cond, then, else_)
+                }
+            };
+            done.push(folded);
+        }
+        done.pop().unwrap()
+    }
 }
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use rand::{Rng, RngExt};
+
+use crate::blambda::eval::apply_binop;
+use crate::blambda::syntax::{BinOp, Expr, Program, UnOp};
+
+const BINOPS: [BinOp; 7] = [
+    BinOp::Or,
+    BinOp::And,
+    BinOp::Xor,
+    BinOp::Nand,
+    BinOp::Nor,
+    BinOp::Implies,
+    BinOp::Iff,
+];
+
+/// Builds a random expression of the given nesting depth that evaluates to
+/// `value`. A depth of zero yields the bare literal.
+pub fn obfuscate_bit<R: Rng>(value: bool, depth: usize, rng: &mut R) -> Expr {
+    if depth == 0 {
+        return Expr::Value(value);
+    }
+    match rng.random_range(0..3) {
+        0 => Expr::Unary {
+            op: UnOp::Not,
+            arg: Arc::new(obfuscate_bit(!value, depth - 1, rng)),
+        },
+        1 => {
+            let op = BINOPS[rng.random_range(0..BINOPS.len())];
+            // every operator has at least one operand pair producing either value
+            let pairs: Vec<(bool, bool)> =
+                [(false, false), (false, true), (true, false), (true, true)]
+                    .into_iter()
+                    .filter(|&(a, b)| apply_binop(op, a, b) == value)
+                    .collect();
+            let (a, b) = pairs[rng.random_range(0..pairs.len())];
+            Expr::Binary {
+                op,
+                arg1: Arc::new(obfuscate_bit(a, depth - 1, rng)),
+                arg2: Arc::new(obfuscate_bit(b, depth - 1, rng)),
+            }
+        }
+        _ => {
+            let cond: bool = rng.random();
+            let untaken: bool = rng.random();
+            let (then, else_) = if cond {
+                (value, untaken)
+            } else {
+                (untaken, value)
+            };
+            Expr::Ternary {
+                cond: Arc::new(obfuscate_bit(cond, depth - 1, rng)),
+                then: Arc::new(obfuscate_bit(then, depth - 1, rng)),
+                else_: Arc::new(obfuscate_bit(else_, depth - 1, rng)),
+            }
+        }
+    }
+}
+
+/// Synthesizes a program with one expression per bit, in program order, so
+/// that `evaluate_bits` of the result yields `bits` again.
+pub fn synthesize_program<R: Rng>(bits: &[bool], depth: usize, rng: &mut R) -> Program {
+    Program {
+        defs: Vec::new(),
+        exprs: bits
+            .iter()
+            .map(|bit| obfuscate_bit(*bit, depth, rng))
+            .collect(),
+    }
+}
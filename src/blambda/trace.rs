@@ -0,0 +1,82 @@
+use std::io::Write;
+
+use crate::blambda::error::BlambdaError;
+use crate::blambda::reduce::{step_traced, Step, Strategy};
+use crate::blambda::serde_ast::serialize_expr;
+use crate::blambda::syntax::Expr;
+
+/// How `eval --trace` prints reduction steps.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum TraceFormat {
+    /// Each intermediate term on its own line.
+    #[default]
+    Text,
+    /// One JSON object per step, one per line.
+    Json,
+}
+
+/// A step as printed by `eval --trace json`.
+#[derive(serde_derive::Serialize)]
+struct StepEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row: Option<usize>,
+    expr: usize,
+    step: usize,
+    redex: String,
+    reduct: String,
+    term: String,
+}
+
+/// Every reduction step taken while evaluating `expr`, in the normal order
+/// that `evaluate_expr` follows. The last step's term is the value of `expr`,
+/// unless evaluation got stuck on an unbound variable.
+pub fn trace_expr(expr: &Expr) -> Vec<Step> {
+    let mut steps: Vec<Step> = Vec::new();
+    while let Some(step) = step_traced(
+        steps.last().map_or(expr, |step| &step.term),
+        Strategy::LeftmostOutermost,
+    ) {
+        steps.push(step);
+    }
+    steps
+}
+
+/// Writes the reduction steps of each expression of a program, labelled with
+/// the row of assignments they were evaluated under if there is one.
+pub fn write_trace(
+    out: &mut impl Write,
+    exprs: &[Expr],
+    row: Option<usize>,
+    format: TraceFormat,
+) -> Result<(), BlambdaError> {
+    for (index, expr) in exprs.iter().enumerate() {
+        let steps = trace_expr(expr);
+        match format {
+            TraceFormat::Text => {
+                if let Some(row) = row {
+                    write!(out, "row {}, ", row)?;
+                }
+                writeln!(out, "expr {}: {}", index, serialize_expr(expr)?)?;
+                for step in &steps {
+                    writeln!(out, "  => {}", serialize_expr(&step.term)?)?;
+                }
+            }
+            TraceFormat::Json => {
+                for (number, step) in steps.iter().enumerate() {
+                    // normal order contracts exactly one redex per step
+                    let contraction = &step.contractions[0];
+                    let event = StepEvent {
+                        row,
+                        expr: index,
+                        step: number + 1,
+                        redex: serialize_expr(&contraction.redex)?,
+                        reduct: serialize_expr(&contraction.reduct)?,
+                        term: serialize_expr(&step.term)?,
+                    };
+                    writeln!(out, "{}", serde_json::to_string(&event)?)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
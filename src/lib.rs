@@ -0,0 +1,29 @@
+//! Parsing, evaluation and formatting of blambda, a boolean lambda calculus.
+//!
+//! ```
+//! let program = blambda::parse_str("x = t ^ f  x & t  ~x").unwrap();
+//! assert_eq!(program.eval(), Ok(vec![true, false]));
+//! assert_eq!(program.format(), "x = (t ^ f) (x & t) (~ x)");
+//! ```
+
+// Without the binary, parts of the internals go unused
+#![cfg_attr(not(feature = "cli"), allow(dead_code))]
+
+mod blambda;
+
+#[cfg(all(test, feature = "cli"))]
+#[allow(clippy::module_inception)]
+mod tests;
+
+pub use blambda::error::{BlambdaError, ErrorLocation, EvalError, Limit};
+pub use blambda::eval::Env;
+pub use blambda::parse::parse_str;
+pub use blambda::syntax::{BinOp, Definition, Expr, Fold, Program, UnOp, Visit, VisitMut};
+
+/// The modules the `blambda` binary is built from. They are not part of the
+/// library's API and may change in any release.
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli {
+    pub use crate::blambda::*;
+}
@@ -1,23 +1,34 @@
-mod blambda;
-
-#[cfg(test)]
-mod tests;
-
-use std::io::Read;
+use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::result::Result;
+use std::time::Duration;
 
-use crate::blambda::format::format_program;
-use crate::blambda::parse::Rule;
-use crate::blambda::{error::BlambdaError, eval::evaluate_program};
-use blambda::parse::BlambdaParser;
-use clap::{Arg, Command};
-use pest::Parser;
-use serde_yaml;
+use blambda::cli::assign::{env_assignments, parse_value, read_assignments, AssignmentFormat};
+use blambda::cli::bdd::count_models;
+use blambda::cli::dag::ProgramDag;
+use blambda::cli::encoding::{encode_bits, unpack_bytes, Encoding};
+use blambda::cli::eval::{
+    evaluate_kleene, unpack_bits, BitOrder, CacheStats, Env, Limits, MemoEvaluator,
+};
+use blambda::cli::format::{format_program_as, Notation};
+use blambda::cli::normalize::{flatten_program, inline_definitions, substitute};
+use blambda::cli::parse::is_ident;
+use blambda::cli::specialize::specialize_program;
+use blambda::cli::synth::synthesize_program;
+use blambda::cli::trace::{write_trace, TraceFormat};
+use blambda::{parse_str, BlambdaError, Program};
+use clap::error::ErrorKind;
+use clap::{Arg, ArgMatches, Command};
+use num_bigint::BigUint;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::fs::File;
 
 type BlambdaResult<T> = Result<T, BlambdaError>;
 
-fn cli() -> BlambdaResult<()> {
-    let matches = Command::new("blambda")
+fn command() -> Command {
+    Command::new("blambda")
         .bin_name("blambda")
         .version("1.0.0")
         .author("David Sillman")
@@ -34,6 +45,37 @@ fn cli() -> BlambdaResult<()> {
                         .required(false)
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Encoding of the printed AST")
+                        .required(false)
+                        .value_parser(["yaml", "json", "sexpr", "cbor"])
+                        .default_value("yaml"),
+                )
+                .arg(
+                    Arg::new("legacy-ternary")
+                        .long("legacy-ternary")
+                        .help("Emit ternaries as nested `condition`/`branch` binary nodes")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("flatten")
+                        .long("flatten")
+                        .help("Flatten chains of `&` and `|` into n-ary nodes")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("input-format")
+                        .long("input-format")
+                        .help("Syntax of the input: blambda source, or a YAML/JSON/CBOR AST")
+                        .required(false)
+                        .value_parser(["blambda", "yaml", "json", "cbor"])
+                        .default_value("blambda"),
+                )
                 .arg(
                     Arg::new("input or filepath")
                         .help("The input or file to parse into an AST")
@@ -51,6 +93,109 @@ fn cli() -> BlambdaResult<()> {
                         .required(false)
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("encoding")
+                        .long("encoding")
+                        .short('e')
+                        .help("Shape in which to print the truth values of the program")
+                        .required(false)
+                        .value_parser([
+                            "decimal", "hex", "octal", "binary", "base64", "raw", "bits",
+                        ])
+                        .default_value("decimal"),
+                )
+                .arg(
+                    Arg::new("bit-order")
+                        .long("bit-order")
+                        .help("Whether the first expression is the most or least significant bit")
+                        .required(false)
+                        .value_parser(["msb", "lsb"])
+                        .default_value("msb"),
+                )
+                .arg(
+                    Arg::new("max-steps")
+                        .long("max-steps")
+                        .help("Give up after evaluating this many sub-expressions")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("max-depth")
+                        .long("max-depth")
+                        .help("Give up on sub-expressions nested deeper than this")
+                        .required(false)
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .help("Give up after evaluating for this many milliseconds")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("trace")
+                        .long("trace")
+                        .help("Print every reduction step to stderr, as text or JSON events")
+                        .required(false)
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .value_parser(["text", "json"])
+                        .default_missing_value("text")
+                        // each step prints the whole term, which no limit bounds
+                        .conflicts_with_all(["max-steps", "max-depth", "timeout"]),
+                )
+                .arg(
+                    Arg::new("stats")
+                        .long("stats")
+                        .help("Print how often evaluation reused a repeated sub-expression to stderr")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("kleene")
+                        .long("kleene")
+                        .help("Evaluate in three-valued logic, printing t, f or u for each expression")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all([
+                            "encoding",
+                            "bit-order",
+                            "max-steps",
+                            "max-depth",
+                            "timeout",
+                            "stats",
+                        ]),
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .help("Variable values, as comma-separated name=t or name=f pairs")
+                        .required(false)
+                        .value_delimiter(',')
+                        .value_parser(parse_assignment)
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("assignments")
+                        .long("assignments")
+                        .help("A YAML, JSON or CSV file of variable values, evaluating once per row")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("env-prefix")
+                        .long("env-prefix")
+                        .help("Read variable values from environment variables with this prefix")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("input-format")
+                        .long("input-format")
+                        .help("Syntax of the input: blambda source, or a YAML/JSON/CBOR AST")
+                        .required(false)
+                        .value_parser(["blambda", "yaml", "json", "cbor"])
+                        .default_value("blambda"),
+                )
                 .arg(
                     Arg::new("input or filepath")
                         .help("The input or file to parse into an AST")
@@ -68,6 +213,73 @@ fn cli() -> BlambdaResult<()> {
                         .required(false)
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("notation")
+                        .long("notation")
+                        .short('n')
+                        .help("Spelling to use for operators and values")
+                        .required(false)
+                        .value_parser(["ascii", "unicode", "keyword"])
+                        .default_value("ascii"),
+                )
+                .arg(
+                    Arg::new("flatten")
+                        .long("flatten")
+                        .help("Flatten chains of `&` and `|` into n-ary nodes")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("input-format")
+                        .long("input-format")
+                        .help("Syntax of the input: blambda source, or a YAML/JSON/CBOR AST")
+                        .required(false)
+                        .value_parser(["blambda", "yaml", "json", "cbor"])
+                        .default_value("blambda"),
+                )
+                .arg(
+                    Arg::new("input or filepath")
+                        .help("The input or file to parse into an AST")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("specialize")
+                .about("Partially evaluate a blambda program, printing the residual program")
+                .arg(
+                    Arg::new("stdin")
+                        .short('s')
+                        .help("Whether the input should be parsed from stdin")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .help("Known variable values, as comma-separated name=t or name=f pairs")
+                        .required(false)
+                        .value_delimiter(',')
+                        .value_parser(parse_assignment)
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("notation")
+                        .long("notation")
+                        .short('n')
+                        .help("Spelling to use for operators and values")
+                        .required(false)
+                        .value_parser(["ascii", "unicode", "keyword"])
+                        .default_value("ascii"),
+                )
+                .arg(
+                    Arg::new("input-format")
+                        .long("input-format")
+                        .help("Syntax of the input: blambda source, or a YAML/JSON/CBOR AST")
+                        .required(false)
+                        .value_parser(["blambda", "yaml", "json", "cbor"])
+                        .default_value("blambda"),
+                )
                 .arg(
                     Arg::new("input or filepath")
                         .help("The input or file to parse into an AST")
@@ -75,88 +287,443 @@ fn cli() -> BlambdaResult<()> {
                         .index(1),
                 ),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("count")
+                .about("Count the satisfying assignments of each expression of a blambda program")
+                .arg(
+                    Arg::new("stdin")
+                        .short('s')
+                        .help("Whether the input should be parsed from stdin")
+                        .required(false)
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("project")
+                        .long("project")
+                        .help("Count only the distinct assignments to these comma-separated variables")
+                        .required(false)
+                        .value_delimiter(',')
+                        .action(clap::ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("input-format")
+                        .long("input-format")
+                        .help("Syntax of the input: blambda source, or a YAML/JSON/CBOR AST")
+                        .required(false)
+                        .value_parser(["blambda", "yaml", "json", "cbor"])
+                        .default_value("blambda"),
+                )
+                .arg(
+                    Arg::new("input or filepath")
+                        .help("The input or file to parse into an AST")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("encode")
+                .about("Synthesize a blambda program which evaluates to the given value")
+                .arg(
+                    Arg::new("bytes")
+                        .long("bytes")
+                        .help("File whose bytes the program should evaluate to")
+                        .required(false)
+                        .conflicts_with("value"),
+                )
+                .arg(
+                    Arg::new("width")
+                        .long("width")
+                        .short('w')
+                        .help("Minimum number of expressions in the program")
+                        .required(false)
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("bit-order")
+                        .long("bit-order")
+                        .help("Whether the first expression is the most or least significant bit")
+                        .required(false)
+                        .value_parser(["msb", "lsb"])
+                        .default_value("msb"),
+                )
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .short('d')
+                        .help("Nesting depth of the random expression obfuscating each bit")
+                        .required(false)
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .help("Seed for the obfuscating expressions, for reproducible output")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("notation")
+                        .long("notation")
+                        .short('n')
+                        .help("Spelling to use for operators and values")
+                        .required(false)
+                        .value_parser(["ascii", "unicode", "keyword"])
+                        .default_value("ascii"),
+                )
+                .arg(
+                    Arg::new("value")
+                        .help("The unsigned decimal integer the program should evaluate to")
+                        .required_unless_present("bytes")
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("repl").about("Start an interactive session for exploring expressions"),
+        )
+        .subcommand(Command::new("lsp").about("Run a language server for blambda files over stdio"))
+}
 
-    use crate::blambda::parse::*;
-    use crate::blambda::syntax::*;
-    use std::fs::File;
+/// Exits with a usage error for options of `subcommand` which cannot be
+/// combined, in cases clap cannot check itself because they depend on an
+/// option's value.
+fn conflict(subcommand: &str, message: &str) -> ! {
+    let mut command = command();
+    command.build();
+    command
+        .find_subcommand_mut(subcommand)
+        .expect("conflicting options belong to a subcommand")
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
+
+fn cli() -> BlambdaResult<()> {
+    let matches = command().get_matches();
+    if let Some((name, submatches)) = matches.subcommand() {
+        // CBOR is binary, so it cannot be passed as a command-line argument
+        let cbor = submatches
+            .try_get_one::<String>("input-format")
+            .ok()
+            .flatten();
+        if cbor.is_some_and(|format| format == "cbor") && submatches.get_flag("stdin") {
+            conflict(
+                name,
+                "the argument '-s' cannot be used with '--input-format cbor'; pass a file instead",
+            );
+        }
+    }
+
+    use blambda::cli::serde_ast::{serialize_program_sexpr, LegacyTernary};
+    use blambda::cli::syntax::*;
 
     match matches.subcommand() {
         Some(("parse", submatches)) => {
-            let from_stdin: bool = *submatches.get_one::<bool>("stdin").unwrap();
-            let input: &str = submatches.get_one::<String>("input or filepath").unwrap();
-
-            if !from_stdin {
-                let mut file = File::open(input).unwrap();
-                let mut contents: String = String::from("");
-                file.read_to_string(&mut contents)
-                    .map_err::<BlambdaError, _>(|e: std::io::Error| e.into())?;
-                let pairs = fallible_parse(Rule::program, &contents)?;
-                let program: Program = parse_program(pairs);
-                println!("{}", serde_yaml::to_string(&program).unwrap());
-                Ok(())
-            } else {
-                let pairs = fallible_parse(Rule::program, input)?;
-                let program: Program = parse_program(pairs);
-                println!("{}", serde_yaml::to_string(&program).unwrap());
-                Ok(())
+            let legacy_ternary: bool = *submatches.get_one::<bool>("legacy-ternary").unwrap();
+            let flatten: bool = *submatches.get_one::<bool>("flatten").unwrap();
+            let output: &str = submatches.get_one::<String>("output").unwrap();
+            if legacy_ternary && output == "sexpr" {
+                conflict(
+                    "parse",
+                    "the argument '--legacy-ternary' cannot be used with '--output sexpr'",
+                );
+            }
+
+            let mut program: Program = read_program(submatches)?;
+            if flatten {
+                program = flatten_program(&program);
+            }
+            match output {
+                "sexpr" => {
+                    println!("{}", serialize_program_sexpr(&program)?);
+                    Ok(())
+                }
+                _ if legacy_ternary => write_ast(&LegacyTernary(&program), output),
+                _ => write_ast(&program, output),
             }
         }
         Some(("eval", submatches)) => {
-            let from_stdin: bool = *submatches.get_one::<bool>("stdin").unwrap();
-            let input: &str = submatches.get_one::<String>("input or filepath").unwrap();
-
-            if !from_stdin {
-                let mut file = File::open(input).unwrap();
-                let mut contents: String = String::from("");
-                file.read_to_string(&mut contents)
-                    .map_err::<BlambdaError, _>(|e: std::io::Error| e.into())?;
-                let pairs = fallible_parse(Rule::program, &contents)?;
-                let program: Program = parse_program(pairs);
-                if let Some(value) = evaluate_program(program) {
-                    println!("{}", value);
-                } else {
-                    println!("Error: program could not be evaluated");
+            let encoding = match submatches.get_one::<String>("encoding").unwrap().as_str() {
+                "hex" => Encoding::Hex,
+                "octal" => Encoding::Octal,
+                "binary" => Encoding::Binary,
+                "base64" => Encoding::Base64,
+                "raw" => Encoding::Raw,
+                "bits" => Encoding::Bits,
+                _ => Encoding::Decimal,
+            };
+            let order = get_bit_order(submatches);
+            let limits = Limits {
+                max_steps: submatches.get_one::<u64>("max-steps").copied(),
+                max_depth: submatches.get_one::<usize>("max-depth").copied(),
+                timeout: submatches
+                    .get_one::<u64>("timeout")
+                    .map(|ms| Duration::from_millis(*ms)),
+            };
+
+            let program: Program = flatten_program(&read_program(submatches)?);
+            let rows = get_eval_envs(submatches)?;
+            // rows are only worth numbering when they come from a file
+            let numbered = submatches.contains_id("assignments");
+            if encoding == Encoding::Raw && rows.len() > 1 {
+                conflict(
+                    "eval",
+                    "'--encoding raw' cannot be used with more than one row of '--assignments', since the rows would run together",
+                );
+            }
+            if let Some(format) = submatches.get_one::<String>("trace") {
+                let format = match format.as_str() {
+                    "json" => TraceFormat::Json,
+                    _ => TraceFormat::Text,
+                };
+                // each row is traced with its values in place of the variables,
+                // numbered only when the rows come from a file
+                let exprs = inline_definitions(&program);
+                let mut stderr = std::io::stderr().lock();
+                for (index, env) in rows.iter().enumerate() {
+                    let bindings: HashMap<String, Expr> = env
+                        .iter()
+                        .map(|(name, value)| (name.clone(), Expr::Value(*value)))
+                        .collect();
+                    let exprs: Vec<Expr> = exprs
+                        .iter()
+                        .map(|expr| substitute(expr, &bindings))
+                        .collect();
+                    write_trace(&mut stderr, &exprs, numbered.then_some(index + 1), format)?;
+                }
+            }
+            if submatches.get_flag("kleene") {
+                for env in &rows {
+                    let values: Vec<String> = evaluate_kleene(&program, env)
+                        .iter()
+                        .map(|value| value.to_string())
+                        .collect();
+                    println!("{}", values.join(" "));
                 }
-                Ok(())
-            } else {
-                let pairs = fallible_parse(Rule::program, input)?;
-                let program: Program = parse_program(pairs);
-                if let Some(value) = evaluate_program(program) {
-                    println!("{}", value);
-                } else {
-                    println!("Error: program could not be evaluated");
+                return Ok(());
+            }
+            let dag = ProgramDag::from_program(&program);
+            let mut evaluator = MemoEvaluator::with_limits(&dag, limits);
+            let results: Vec<_> = rows.iter().map(|env| evaluator.eval_all(env)).collect();
+            if submatches.get_flag("stats") {
+                let CacheStats { hits, misses } = evaluator.stats;
+                eprintln!(
+                    "{} distinct sub-expressions, {} cache hits, {} misses",
+                    dag.arena.len(),
+                    hits,
+                    misses
+                );
+            }
+            let results = results
+                .into_iter()
+                .enumerate()
+                .map(|(index, result)| {
+                    result.map_err(|error| match numbered {
+                        true => BlambdaError::new(format!("row {}: {}", index + 1, error)),
+                        false => error.into(),
+                    })
+                })
+                .collect::<BlambdaResult<Vec<_>>>()?;
+            let mut stdout = std::io::stdout().lock();
+            for bits in results {
+                stdout.write_all(&encode_bits(&bits, order, encoding))?;
+                if encoding != Encoding::Raw {
+                    writeln!(stdout)?;
                 }
-                Ok(())
             }
+            stdout.flush()?;
+            Ok(())
         }
         Some(("format", submatches)) => {
-            let from_stdin: bool = *submatches.get_one::<bool>("stdin").unwrap();
-            let input: &str = submatches.get_one::<String>("input or filepath").unwrap();
-
-            if !from_stdin {
-                let mut file = File::open(input).unwrap();
-                let mut contents: String = String::from("");
-                file.read_to_string(&mut contents)
-                    .map_err::<BlambdaError, _>(|e: std::io::Error| e.into())?;
-                let pairs = fallible_parse(Rule::program, &contents)?;
-                let program: Program = parse_program(pairs);
-                println!("{}", format_program(&program));
-                Ok(())
-            } else {
-                let pairs = fallible_parse(Rule::program, input)?;
-                let program: Program = parse_program(pairs);
-                println!("{}", format_program(&program));
-                Ok(())
+            let notation = get_notation(submatches);
+            let flatten: bool = *submatches.get_one::<bool>("flatten").unwrap();
+
+            let mut program: Program = read_program(submatches)?;
+            if flatten {
+                program = flatten_program(&program);
             }
+            println!("{}", format_program_as(&program, notation));
+            Ok(())
         }
+        Some(("specialize", submatches)) => {
+            let env = get_assignments(submatches);
+            let program = specialize_program(&read_program(submatches)?, &env);
+            println!("{}", format_program_as(&program, get_notation(submatches)));
+            Ok(())
+        }
+        Some(("count", submatches)) => {
+            let projection: Option<Vec<String>> = submatches
+                .get_many::<String>("project")
+                .map(|names| names.map(|name| name.trim().to_string()).collect());
+            let program = read_program(submatches)?;
+            for models in count_models(&program, projection.as_deref())? {
+                println!("{}", models);
+            }
+            Ok(())
+        }
+        Some(("encode", submatches)) => {
+            let width: usize = *submatches.get_one::<usize>("width").unwrap();
+            let depth: usize = *submatches.get_one::<usize>("depth").unwrap();
+            let seed: u64 = match submatches.get_one::<u64>("seed") {
+                Some(seed) => *seed,
+                None => rand::random(),
+            };
+            let order = get_bit_order(submatches);
+            let notation = get_notation(submatches);
+
+            let mut bits: Vec<bool> = match submatches.get_one::<String>("bytes") {
+                Some(path) => unpack_bytes(&std::fs::read(path)?, order),
+                None => {
+                    let value: &str = submatches.get_one::<String>("value").unwrap();
+                    let value = BigUint::parse_bytes(value.as_bytes(), 10).ok_or_else(|| {
+                        BlambdaError::new(format!("invalid unsigned integer `{}`", value))
+                    })?;
+                    unpack_bits(&value, width, order)
+                }
+            };
+            // pad byte input out to the requested width with leading zeros
+            if bits.len() < width {
+                let padding = vec![false; width - bits.len()];
+                bits = match order {
+                    BitOrder::Msb => [padding, bits].concat(),
+                    BitOrder::Lsb => [bits, padding].concat(),
+                };
+            }
+            // only an empty `--bytes` file gets here without a single bit
+            if bits.is_empty() {
+                return Err(BlambdaError::new(
+                    "cannot encode an empty file, since a program needs at least one expression; \
+                     pass --width to encode it as zeros",
+                ));
+            }
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            let program = synthesize_program(&bits, depth, &mut rng);
+            println!("{}", format_program_as(&program, notation));
+            Ok(())
+        }
+        Some(("repl", _)) => blambda::cli::repl::run(),
+        Some(("lsp", _)) => blambda::cli::lsp::run(),
         Some((_, _)) => unreachable!(),
         None => unreachable!(),
     }
 }
 
-fn fallible_parse(rule: Rule, input: &str) -> Result<pest::iterators::Pairs<Rule>, BlambdaError> {
-    BlambdaParser::parse(rule, &input).map_err(|e| e.into())
+/// Reads and parses the program named by a subcommand's `-s` flag and
+/// positional input, either inline or from a file, in its `--input-format`.
+fn read_program(submatches: &ArgMatches) -> BlambdaResult<Program> {
+    let from_stdin: bool = *submatches.get_one::<bool>("stdin").unwrap();
+    let input: &str = submatches.get_one::<String>("input or filepath").unwrap();
+    let input_format: &str = submatches.get_one::<String>("input-format").unwrap();
+
+    // CBOR is binary, so it is decoded straight from the file's bytes; `cli`
+    // rejects it with `-s`
+    if input_format == "cbor" {
+        return Ok(ciborium::from_reader(std::io::BufReader::new(File::open(
+            input,
+        )?))?);
+    }
+
+    let mut contents: String = String::from("");
+    let source: &str = if !from_stdin {
+        let mut file = File::open(input)?;
+        file.read_to_string(&mut contents)?;
+        &contents
+    } else {
+        input
+    };
+
+    match input_format {
+        "yaml" => Ok(serde_yaml::from_str(source)?),
+        "json" => Ok(serde_json::from_str(source)?),
+        _ => parse_str(source),
+    }
+}
+
+fn get_notation(submatches: &ArgMatches) -> Notation {
+    match submatches.get_one::<String>("notation").unwrap().as_str() {
+        "unicode" => Notation::Unicode,
+        "keyword" => Notation::Keyword,
+        _ => Notation::Ascii,
+    }
+}
+
+/// Parses a `name=value` assignment of `--set`, where the name is a
+/// variable name and the value is any spelling of `t` or `f`.
+fn parse_assignment(assignment: &str) -> Result<(String, bool), String> {
+    let (name, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, got `{}`", assignment))?;
+    let name = name.trim();
+    if !is_ident(name) {
+        return Err(format!("`{}` is not a variable name", name));
+    }
+    match parse_value(value) {
+        Some(value) => Ok((name.to_string(), value)),
+        None => Err(format!("`{}` is not t or f", value)),
+    }
+}
+
+fn get_assignments(submatches: &ArgMatches) -> Env {
+    submatches
+        .get_many::<(String, bool)>("set")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// The environments `eval` runs in: one per row of `--assignments`, or a
+/// single one without it. `--set` takes precedence over the rows, which take
+/// precedence over `--env-prefix`.
+fn get_eval_envs(submatches: &ArgMatches) -> BlambdaResult<Vec<Env>> {
+    let base = match submatches.get_one::<String>("env-prefix") {
+        Some(prefix) => env_assignments(prefix, std::env::vars())?,
+        None => Env::new(),
+    };
+    let rows = match submatches.get_one::<String>("assignments") {
+        Some(path) => {
+            let format = AssignmentFormat::from_path(path).ok_or_else(|| {
+                BlambdaError::new(format!(
+                    "cannot tell the format of `{}`; expected a .yaml, .json or .csv file",
+                    path
+                ))
+            })?;
+            read_assignments(std::io::BufReader::new(File::open(path)?), format)?
+        }
+        None => vec![Env::new()],
+    };
+    let set = get_assignments(submatches);
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let mut env = base.clone();
+            env.extend(row);
+            env.extend(set.clone());
+            env
+        })
+        .collect())
+}
+
+fn get_bit_order(submatches: &ArgMatches) -> BitOrder {
+    match submatches.get_one::<String>("bit-order").unwrap().as_str() {
+        "lsb" => BitOrder::Lsb,
+        _ => BitOrder::Msb,
+    }
+}
+
+/// Prints a serializable AST to stdout in the `--output` encoding of `parse`.
+fn write_ast<T: Serialize>(ast: &T, output: &str) -> BlambdaResult<()> {
+    match output {
+        "json" => println!("{}", serde_json::to_string_pretty(ast)?),
+        "cbor" => {
+            let mut stdout = std::io::stdout().lock();
+            ciborium::into_writer(ast, &mut stdout)?;
+            stdout.flush()?;
+        }
+        _ => println!("{}", serde_yaml::to_string(ast)?),
+    }
+    Ok(())
 }
 
 fn main() {
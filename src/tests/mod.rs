@@ -2,12 +2,77 @@
 mod tests {
     use std::sync::Arc;
 
-    use crate::blambda::eval::evaluate_expr;
+    use crate::blambda::assign::{env_assignments, read_assignments, AssignmentFormat};
+    use crate::blambda::batch::{compile_batch, truth_table_bits, MAX_BIT_TABLE_VARS};
+    use crate::blambda::bdd::count_models;
+    use crate::blambda::compile::{compile, Op, Vm};
+    use crate::blambda::dag::{ExprArena, Node, ProgramDag};
+    use crate::blambda::encoding::{encode_bits, pack_bytes, unpack_bytes, Encoding};
+    use crate::blambda::error::{EvalError, Limit};
+    use crate::blambda::eval::{
+        apply_binop, apply_kleene_binop, evaluate_bits, evaluate_bits_with, evaluate_expr,
+        evaluate_expr_in, evaluate_kleene, evaluate_kleene_expr, evaluate_program, pack_bits,
+        truth_table, unpack_bits, BitOrder, CacheStats, Env, Kleene, Limits, MemoEvaluator,
+        MAX_TRUTH_TABLE_VARS,
+    };
     use crate::blambda::format::format_expr;
     use crate::blambda::format::format_program;
+    use crate::blambda::format::{format_expr_as, format_program_as, Notation};
+    use crate::blambda::lsp::serve;
+    use crate::blambda::normalize::{flatten_expr, flatten_program, free_vars, inline_definitions};
     use crate::blambda::parse::*;
+    use crate::blambda::reduce::{reduce, step, step_traced, step_with, Strategy};
+    use crate::blambda::repl::{Reply, Session};
+    use crate::blambda::serde_ast::{serialize_program_sexpr, serialize_sexpr, LegacyTernary};
+    use crate::blambda::specialize::{specialize_expr, specialize_program};
     use crate::blambda::syntax::*;
-    use crate::fallible_parse;
+    use crate::blambda::synth::synthesize_program;
+    use crate::blambda::trace::{trace_expr, write_trace, TraceFormat};
+    use lsp_server::{Connection, Message, Notification, Request};
+    use num_bigint::BigUint;
+    use rand::rngs::StdRng;
+    use rand::{Rng, RngExt, SeedableRng};
+
+    /// Builds a random expression over the given variables, using every kind
+    /// of node.
+    fn random_expr<R: Rng>(depth: usize, vars: &[&str], rng: &mut R) -> Expr {
+        if depth == 0 {
+            return match rng.random_range(0..3) {
+                0 => Expr::Value(rng.random()),
+                _ => Expr::Var(vars[rng.random_range(0..vars.len())].to_string()),
+            };
+        }
+        let child = |rng: &mut R| random_expr(depth - 1, vars, rng);
+        match rng.random_range(0..5) {
+            0 => Expr::Unary {
+                op: UnOp::Not,
+                arg: Arc::new(child(rng)),
+            },
+            1 => {
+                let ops = [
+                    BinOp::Or,
+                    BinOp::And,
+                    BinOp::Xor,
+                    BinOp::Nand,
+                    BinOp::Nor,
+                    BinOp::Implies,
+                    BinOp::Iff,
+                ];
+                Expr::Binary {
+                    op: ops[rng.random_range(0..ops.len())],
+                    arg1: Arc::new(child(rng)),
+                    arg2: Arc::new(child(rng)),
+                }
+            }
+            2 => Expr::And((0..rng.random_range(0..4)).map(|_| child(rng)).collect()),
+            3 => Expr::Or((0..rng.random_range(0..4)).map(|_| child(rng)).collect()),
+            _ => Expr::Ternary {
+                cond: Arc::new(child(rng)),
+                then: Arc::new(child(rng)),
+                else_: Arc::new(child(rng)),
+            },
+        }
+    }
 
     #[test]
     fn test_serialize_value_expr() {
@@ -53,29 +118,109 @@ mod tests {
         let serialized = serde_yaml::to_string(&expr).unwrap();
         assert_eq!(serialized, "op: and\narg1: true\narg2: false\n");
 
-        // Serialize binary CONDITION as yaml
-        let expr = Expr::Binary {
-            op: BinOp::Condition,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Value(false)),
+        // Serialize the extended binary operators as yaml
+        let cases = [
+            (BinOp::Xor, "xor"),
+            (BinOp::Nand, "nand"),
+            (BinOp::Nor, "nor"),
+            (BinOp::Implies, "implies"),
+            (BinOp::Iff, "iff"),
+        ];
+        cases.iter().for_each(|&(op, name)| {
+            let expr = Expr::Binary {
+                op,
+                arg1: Arc::new(Expr::Value(true)),
+                arg2: Arc::new(Expr::Value(false)),
+            };
+            let serialized = serde_yaml::to_string(&expr).unwrap();
+            assert_eq!(
+                serialized,
+                format!("op: {}\narg1: true\narg2: false\n", name)
+            );
+        });
+    }
+
+    #[test]
+    fn test_serialize_ternary_expr() {
+        // Serialize ternary as yaml
+        let expr = Expr::Ternary {
+            cond: Arc::new(Expr::Value(true)),
+            then: Arc::new(Expr::Value(false)),
+            else_: Arc::new(Expr::Value(true)),
         };
         let serialized = serde_yaml::to_string(&expr).unwrap();
-        assert_eq!(serialized, "op: condition\narg1: true\narg2: false\n");
+        assert_eq!(
+            serialized,
+            "op: ternary\ncond: true\nthen: false\nelse: true\n"
+        );
 
-        // Serialize binary BRANCH as yaml
-        let expr = Expr::Binary {
-            op: BinOp::Branch,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Value(false)),
+        // Serialize ternary in the legacy condition/branch shape as yaml
+        let serialized = serde_yaml::to_string(&LegacyTernary(&expr)).unwrap();
+        assert_eq!(
+            serialized,
+            "op: condition\narg1: true\narg2:\n  op: branch\n  arg1: false\n  arg2: true\n"
+        );
+
+        // Legacy shape applies to ternaries nested anywhere in a program
+        let program = Program {
+            defs: vec![],
+            exprs: vec![Expr::Unary {
+                op: UnOp::Not,
+                arg: Arc::new(expr),
+            }],
         };
+        let serialized = serde_yaml::to_string(&LegacyTernary(&program)).unwrap();
+        assert_eq!(
+            serialized,
+            "exprs:\n- op: not\n  arg:\n    op: condition\n    arg1: true\n    arg2:\n      op: branch\n      arg1: false\n      arg2: true\n"
+        );
+    }
+
+    #[test]
+    fn test_serialize_nary_expr() {
+        // Serialize n-ary AND as yaml
+        let expr = Expr::And(vec![
+            Expr::Value(true),
+            Expr::Value(false),
+            Expr::Value(true),
+        ]);
         let serialized = serde_yaml::to_string(&expr).unwrap();
-        assert_eq!(serialized, "op: branch\narg1: true\narg2: false\n");
+        assert_eq!(serialized, "op: and\nargs:\n- true\n- false\n- true\n");
+
+        // Serialize n-ary OR as yaml
+        let expr = Expr::Or(vec![Expr::Value(false), Expr::Value(true)]);
+        let serialized = serde_yaml::to_string(&expr).unwrap();
+        assert_eq!(serialized, "op: or\nargs:\n- false\n- true\n");
+    }
+
+    #[test]
+    fn test_serialize_definitions() {
+        // Serialize a program with definitions as yaml
+        let input = "x = ~y  x";
+        let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+        let serialized = serde_yaml::to_string(&program).unwrap();
+        assert_eq!(
+            serialized,
+            "defs:\n- name: x\n  body:\n    op: not\n    arg: y\nexprs:\n- x\n"
+        );
+        assert_eq!(
+            serde_yaml::from_str::<Program>(&serialized).unwrap(),
+            program
+        );
+
+        // Format and S-expressions list the definitions first
+        assert_eq!(format_program(&program), "x = (~ y) x");
+        assert_eq!(
+            serialize_program_sexpr(&program).unwrap(),
+            "(define x (not y))\nx"
+        );
     }
 
     #[test]
     fn test_serialize_program() {
         // Serialize program with one value expression as yaml
         let program = Program {
+            defs: vec![],
             exprs: vec![Expr::Value(true)],
         };
         let serialized = serde_yaml::to_string(&program).unwrap();
@@ -83,6 +228,7 @@ mod tests {
 
         // Serialize program with two unary expressions as yaml
         let program = Program {
+            defs: vec![],
             exprs: vec![
                 Expr::Unary {
                     op: UnOp::Not,
@@ -101,18 +247,182 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deserialize_roundtrip() {
+        // Deserializing a serialized program yields the same program, in yaml and json
+        let inputs = [
+            "t f",
+            "~t (t | f) (t & f) (t ^ f) (t !& f) (t !| f) (t -> f) (t <-> f)",
+            "t ? (f ? t : f) : ~(t | f)",
+            "t & f & t | f | ~(t | t | t)",
+        ];
+        inputs.iter().for_each(|&input| {
+            let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+            [program.clone(), flatten_program(&program)]
+                .iter()
+                .for_each(|program| {
+                    let yaml = serde_yaml::to_string(program).unwrap();
+                    assert_eq!(&serde_yaml::from_str::<Program>(&yaml).unwrap(), program);
+                    let json = serde_json::to_string(program).unwrap();
+                    assert_eq!(&serde_json::from_str::<Program>(&json).unwrap(), program);
+                });
+        });
+    }
+
+    #[test]
+    fn test_cbor_roundtrip() {
+        // Encoding a program as CBOR and decoding it yields the same program
+        let input = "t ? (f ? t : f) : ~(t | f) (t <-> f) (t & f & t)";
+        let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+        let program = flatten_program(&program);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&program, &mut bytes).unwrap();
+        assert_eq!(
+            ciborium::from_reader::<Program, _>(bytes.as_slice()).unwrap(),
+            program
+        );
+    }
+
+    #[test]
+    fn test_deserialize_legacy_ternary() {
+        // The legacy condition/branch shape deserializes to a ternary node
+        let program = parse_program(fallible_parse(Rule::program, "t ? f : t").unwrap()).unwrap();
+        let yaml = serde_yaml::to_string(&LegacyTernary(&program)).unwrap();
+        assert_eq!(serde_yaml::from_str::<Program>(&yaml).unwrap(), program);
+    }
+
+    #[test]
+    fn test_deserialize_bad_ast() {
+        // Malformed ASTs are rejected with a description of the problem
+        let cases = [
+            (
+                r#"{"exprs": [{"op": "xr", "arg1": true, "arg2": false}]}"#,
+                "unknown op `xr`",
+            ),
+            (r#"{"exprs": [{"op": "not"}]}"#, "missing field `arg`"),
+            (
+                r#"{"exprs": [{"op": "xor", "args": [true]}]}"#,
+                "does not take `args`",
+            ),
+            (
+                r#"{"exprs": [{"op": "branch", "arg1": true, "arg2": false}]}"#,
+                "outside of a `condition`",
+            ),
+            (
+                r#"{"exprs": [{"op": "not", "arg": true, "extra": 1}]}"#,
+                "unknown field `extra`",
+            ),
+            (
+                r#"{"exprs": [1]}"#,
+                "a boolean, null, a variable name or a map",
+            ),
+            // operands that the node's op does not take
+            (
+                r#"{"exprs": [{"op": "not", "arg": true, "arg1": true}]}"#,
+                "`not` node does not take `arg1`",
+            ),
+            (
+                r#"{"exprs": [{"op": "and", "args": [true], "arg1": true, "arg2": false}]}"#,
+                "`and` node does not take `arg1`",
+            ),
+            (
+                r#"{"exprs": [{"op": "or", "arg1": true, "arg2": false, "cond": true}]}"#,
+                "`or` node does not take `cond`",
+            ),
+            (
+                r#"{"exprs": [{"op": "ternary", "cond": true, "then": true, "else": false, "arg": true}]}"#,
+                "`ternary` node does not take `arg`",
+            ),
+            (
+                r#"{"exprs": [{"op": "condition", "arg1": true, "arg2": {"op": "branch", "arg1": true, "arg2": false, "args": []}}]}"#,
+                "`branch` node does not take `args`",
+            ),
+            // names the source syntax cannot spell, since they would not
+            // survive formatting and parsing again
+            (r#"{"exprs": ["t"]}"#, "invalid value: string \"t\""),
+            (r#"{"exprs": ["U"]}"#, "invalid value: string \"U\""),
+            (
+                r#"{"exprs": [{"op": "and", "arg1": "a", "arg2": "and"}]}"#,
+                "invalid value: string \"and\"",
+            ),
+            (r#"{"exprs": ["a b"]}"#, "invalid value: string \"a b\""),
+            (r#"{"exprs": [""]}"#, "expected a variable name"),
+            (
+                r#"{"defs": [{"name": "t", "body": true}], "exprs": [true]}"#,
+                "invalid value: string \"t\"",
+            ),
+            (
+                r#"{"defs": [{"name": "unknown", "body": true}], "exprs": [true]}"#,
+                "invalid value: string \"unknown\"",
+            ),
+            (
+                r#"{"defs": [{"name": "x y", "body": true}], "exprs": [true]}"#,
+                "invalid value: string \"x y\", expected a variable name",
+            ),
+            (
+                r#"{"defs": [{"name": "t", "body": "a b"}], "exprs": [{"op": "and", "arg1": "t", "arg2": "and"}]}"#,
+                "invalid value: string \"a b\"",
+            ),
+            // definitions that refer to themselves or to later definitions
+            (
+                r#"{"defs": [{"name": "x", "body": "y"}, {"name": "y", "body": true}], "exprs": ["x"]}"#,
+                "`y` is used before its definition",
+            ),
+            (
+                r#"{"defs": [{"name": "x", "body": {"op": "not", "arg": "x"}}], "exprs": ["x"]}"#,
+                "`x` is used before its definition",
+            ),
+        ];
+        cases.iter().for_each(|&(input, message)| {
+            let error = serde_json::from_str::<Program>(input).unwrap_err();
+            assert!(
+                error.to_string().contains(message),
+                "{:?} gave {}",
+                input,
+                error
+            );
+        });
+    }
+
+    #[test]
+    fn test_serialize_sexpr() {
+        // Serialize expressions as S-expressions
+        let cases = [
+            ("t", "t"),
+            ("t | ~f", "(or t (not f))"),
+            ("t -> f <-> t !& f", "(iff (implies t f) (nand t f))"),
+            ("t ? f : ~t", "(ternary t f (not t))"),
+        ];
+        cases.iter().for_each(|&(input, expected)| {
+            let expr = parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+            assert_eq!(serialize_sexpr(&expr).unwrap(), expected);
+        });
+
+        // N-ary nodes list every operand
+        let expr = Expr::And(vec![
+            Expr::Value(true),
+            Expr::Value(false),
+            Expr::Value(true),
+        ]);
+        assert_eq!(serialize_sexpr(&expr).unwrap(), "(and t f t)");
+
+        // Programs serialize one expression per line
+        let program = parse_program(fallible_parse(Rule::program, "t (f ^ t)").unwrap()).unwrap();
+        assert_eq!(serialize_program_sexpr(&program).unwrap(), "t\n(xor f t)");
+    }
+
     #[test]
     fn test_parse_value_expr() {
         // Parse true from blambda script
         let input = "t";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(expr, Expr::Value(true));
 
         // Parse false from blambda script
         let input = "f";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(expr, Expr::Value(false));
     }
 
@@ -121,7 +431,7 @@ mod tests {
         // Parse unary NOT from blambda script
         let input = "~t";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(
             expr,
             Expr::Unary {
@@ -136,7 +446,7 @@ mod tests {
         // Parse binary OR from blambda script
         let input = "t | f";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(
             expr,
             Expr::Binary {
@@ -149,7 +459,7 @@ mod tests {
         // Parse binary AND from blambda script
         let input = "t & f";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(
             expr,
             Expr::Binary {
@@ -158,32 +468,205 @@ mod tests {
                 arg2: Arc::new(Expr::Value(false)),
             }
         );
+    }
 
-        // Parse binary CONDITION from blambda script
-        let input = "t ? f";
+    #[test]
+    fn test_parse_extended_binary_expr() {
+        // Parse each of the extended boolean operators from blambda script
+        let cases = [
+            ("t ^ f", BinOp::Xor),
+            ("t !& f", BinOp::Nand),
+            ("t !| f", BinOp::Nor),
+            ("t -> f", BinOp::Implies),
+            ("t <-> f", BinOp::Iff),
+        ];
+        cases.iter().for_each(|&(input, op)| {
+            let pairs = fallible_parse(Rule::expr, input).unwrap();
+            let expr = parse_expr(pairs).unwrap();
+            assert_eq!(
+                expr,
+                Expr::Binary {
+                    op,
+                    arg1: Arc::new(Expr::Value(true)),
+                    arg2: Arc::new(Expr::Value(false)),
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn test_parse_extended_binary_precedence() {
+        // Implication binds looser than the other binary ops
+        let input = "t | f -> f";
+        let pairs = fallible_parse(Rule::expr, input).unwrap();
+        let expr = parse_expr(pairs).unwrap();
+        assert_eq!(format_expr(&expr), "((t | f) -> f)");
+
+        // Implication is right-associative
+        let input = "t -> f -> t";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
+        assert_eq!(format_expr(&expr), "(t -> (f -> t))");
+
+        // Biconditional binds looser than implication
+        let input = "t -> f <-> f ^ t";
+        let pairs = fallible_parse(Rule::expr, input).unwrap();
+        let expr = parse_expr(pairs).unwrap();
+        assert_eq!(format_expr(&expr), "((t -> f) <-> (f ^ t))");
+
+        // Ternary operator still binds loosest of all
+        let input = "t <-> f ? t : f";
+        let pairs = fallible_parse(Rule::expr, input).unwrap();
+        let expr = parse_expr(pairs).unwrap();
+        assert_eq!(format_expr(&expr), "((t <-> f) ? t : f)");
+    }
+
+    #[test]
+    fn test_parse_operator_aliases() {
+        // Unicode and keyword spellings parse to the same AST as their ASCII forms
+        let cases = [
+            ("~t", vec!["¬t", "not t", "NOT t", "~true", "~⊤"]),
+            ("t & f", vec!["t ∧ f", "t and f", "true and false", "⊤ ∧ ⊥"]),
+            ("t | f", vec!["t ∨ f", "t or f", "T Or F"]),
+            ("t ^ f", vec!["t ⊕ f", "t xor f"]),
+            ("t !& f", vec!["t ↑ f", "t nand f"]),
+            ("t !| f", vec!["t ↓ f", "t nor f"]),
+            ("t -> f", vec!["t → f", "t implies f"]),
+            ("t <-> f", vec!["t ↔ f", "t iff f"]),
+        ];
+        cases.iter().for_each(|(ascii, aliases)| {
+            let expected = parse_expr(fallible_parse(Rule::expr, ascii).unwrap()).unwrap();
+            aliases.iter().for_each(|alias| {
+                let pairs = fallible_parse(Rule::expr, alias).unwrap();
+                assert_eq!(parse_expr(pairs).unwrap(), expected, "alias {:?}", alias);
+            });
+        });
+
+        // Keyword operators must be delimited from the following word, which
+        // otherwise reads as a variable name
+        let pairs = fallible_parse(Rule::program, "t andf nott").unwrap();
         assert_eq!(
-            expr,
-            Expr::Binary {
-                op: BinOp::Condition,
-                arg1: Arc::new(Expr::Value(true)),
-                arg2: Arc::new(Expr::Value(false)),
-            }
+            parse_program(pairs).unwrap().exprs,
+            vec![
+                Expr::Value(true),
+                Expr::Var("andf".to_string()),
+                Expr::Var("nott".to_string()),
+            ]
         );
+    }
 
-        // Parse binary BRANCH from blambda script
-        let input = "t : f";
-        let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+    #[test]
+    fn test_parse_definitions() {
+        // Parse definitions alongside the expressions that use them
+        let input = "x = t & f  y = ~x  x | y  z";
+        let pairs = fallible_parse(Rule::program, input).unwrap();
+        let program = parse_program(pairs).unwrap();
         assert_eq!(
-            expr,
-            Expr::Binary {
-                op: BinOp::Branch,
-                arg1: Arc::new(Expr::Value(true)),
-                arg2: Arc::new(Expr::Value(false)),
+            program,
+            Program {
+                defs: vec![
+                    Definition {
+                        name: "x".to_string(),
+                        body: Expr::Binary {
+                            op: BinOp::And,
+                            arg1: Arc::new(Expr::Value(true)),
+                            arg2: Arc::new(Expr::Value(false)),
+                        },
+                    },
+                    Definition {
+                        name: "y".to_string(),
+                        body: Expr::Unary {
+                            op: UnOp::Not,
+                            arg: Arc::new(Expr::Var("x".to_string())),
+                        },
+                    },
+                ],
+                exprs: vec![
+                    Expr::Binary {
+                        op: BinOp::Or,
+                        arg1: Arc::new(Expr::Var("x".to_string())),
+                        arg2: Arc::new(Expr::Var("y".to_string())),
+                    },
+                    Expr::Var("z".to_string()),
+                ],
             }
         );
+
+        // Only whole identifiers which are not keywords are names
+        assert!(["x", "_x", "tfx", "u1", "x_2"].into_iter().all(is_ident));
+        assert!(!["", "1x", "x y", "x&y", "t", "F", "unknown", " x", "tf"]
+            .into_iter()
+            .any(is_ident));
+
+        // Names cannot be defined twice
+        let pairs = fallible_parse(Rule::program, "x = t x = f x").unwrap();
+        let error = parse_program(pairs).unwrap_err();
+        assert!(format!("{}", error).contains("`x` is already defined"));
+
+        // Definitions only apply to what follows them, so earlier uses are rejected
+        for input in [
+            "a  a = t",
+            "x = y  y = t  x",
+            "x = ~x  x",
+            "b & (c | a)  a = t",
+        ] {
+            let error = parse_str(input).unwrap_err();
+            assert!(format!("{}", error).contains("is used before its definition"));
+        }
+        let error = parse_str("t | a  a = t  a").unwrap_err();
+        assert_eq!(error.location().unwrap().span, 4..5);
+
+        // Values run together, which used to be read as several values, are an
+        // error rather than a variable, so old programs do not change meaning
+        for input in ["tf", "t FT", "tt = t", "a & (ff | t)", "ttf ? t : f"] {
+            let error = parse_str(input).unwrap_err();
+            assert!(
+                format!("{}", error).contains("is values run together"),
+                "{:?} gave {}",
+                input,
+                error
+            );
+        }
+        let error = parse_str("t FT").unwrap_err();
+        assert_eq!(error.location().unwrap().span, 2..4);
+        assert!(format!("{}", error).contains("write `F T`"));
+        assert_eq!(
+            parse_str("tfx t_f t1").unwrap().exprs,
+            [Expr::var("tfx"), Expr::var("t_f"), Expr::var("t1")]
+        );
+
+        // Keywords are not variable names
+        assert!(fallible_parse(Rule::program, "and = t").is_err());
+        assert!(fallible_parse(Rule::program, "T = t").is_err());
+    }
+
+    #[test]
+    fn test_expr_builders() {
+        let (a, b, c) = (Expr::var("a"), Expr::var("b"), Expr::var("c"));
+
+        // Operators build the same trees as the parser
+        let expr = Expr::ite(
+            a.clone() & !b.clone(),
+            c.clone() ^ true.into(),
+            a.clone() | b.clone(),
+        );
+        assert_eq!("a & ~b ? c ^ t : a | b".parse::<Expr>().unwrap(), expr);
+        assert_eq!(
+            "(a !& b) -> (b !| c) <-> a".parse::<Expr>().unwrap(),
+            a.clone().nand(b.clone()).implies(b.nor(c)).iff(a)
+        );
+
+        // Display and FromStr round trip
+        assert_eq!(expr.to_string(), "((a & (~ b)) ? (c ^ t) : (a | b))");
+        assert_eq!(expr.to_string().parse::<Expr>().unwrap(), expr);
+        let program: Program = "x = t & f  x | y".parse().unwrap();
+        assert_eq!(program.defs[0].to_string(), "x = (t & f)");
+        assert_eq!(program.to_string().parse::<Program>().unwrap(), program);
+
+        // Expressions parse from exactly one expression
+        assert!("a b".parse::<Expr>().is_err());
+        assert!("x = t  x".parse::<Expr>().is_err());
+        assert!("a &".parse::<Expr>().is_err());
     }
 
     #[test]
@@ -191,44 +674,83 @@ mod tests {
         // Parse ternary operator from blambda script
         let input = "t ? f : t";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(
             expr,
-            Expr::Binary {
-                op: BinOp::Condition,
-                arg1: Arc::new(Expr::Value(true)),
-                arg2: Arc::new(Expr::Binary {
-                    op: BinOp::Branch,
-                    arg1: Arc::new(Expr::Value(false)),
-                    arg2: Arc::new(Expr::Value(true)),
-                }),
+            Expr::Ternary {
+                cond: Arc::new(Expr::Value(true)),
+                then: Arc::new(Expr::Value(false)),
+                else_: Arc::new(Expr::Value(true)),
             }
         );
 
         // Parse nested ternary operator from blambda script
         let input = "t ? (f ? t : f) : t";
         let pairs = fallible_parse(Rule::expr, input).unwrap();
-        let expr = parse_expr(pairs);
+        let expr = parse_expr(pairs).unwrap();
         assert_eq!(
             expr,
-            Expr::Binary {
-                op: BinOp::Condition,
-                arg1: Arc::new(Expr::Value(true)),
-                arg2: Arc::new(Expr::Binary {
-                    op: BinOp::Branch,
-                    arg1: Arc::new(Expr::Binary {
-                        op: BinOp::Condition,
-                        arg1: Arc::new(Expr::Value(false)),
-                        arg2: Arc::new(Expr::Binary {
-                            op: BinOp::Branch,
-                            arg1: Arc::new(Expr::Value(true)),
-                            arg2: Arc::new(Expr::Value(false)),
-                        }),
-                    }),
-                    arg2: Arc::new(Expr::Value(true)),
+            Expr::Ternary {
+                cond: Arc::new(Expr::Value(true)),
+                then: Arc::new(Expr::Ternary {
+                    cond: Arc::new(Expr::Value(false)),
+                    then: Arc::new(Expr::Value(true)),
+                    else_: Arc::new(Expr::Value(false)),
                 }),
+                else_: Arc::new(Expr::Value(true)),
             }
         );
+
+        // Unparenthesized ternaries nest like they do in C
+        let cases = [
+            ("t ? f : t ? t : f", "(t ? f : (t ? t : f))"),
+            ("t ? f ? t : f : t", "(t ? (f ? t : f) : t)"),
+            ("t | f ? ~t : f & t", "((t | f) ? (~ t) : (f & t))"),
+            // earlier releases formatted ternaries with the branches parenthesised
+            ("t ? (f : t)", "(t ? f : t)"),
+            (
+                "t ? ((f ? (t : f)) : (t ? (f : t)))",
+                "(t ? (f ? t : f) : (t ? f : t))",
+            ),
+        ];
+        cases.iter().for_each(|&(input, expected)| {
+            let pairs = fallible_parse(Rule::expr, input).unwrap();
+            let expr = parse_expr(pairs).unwrap();
+            assert_eq!(format_expr(&expr), expected);
+        });
+
+        // So files they formatted still parse, to the same program
+        let legacy = "((t | (~ f)) ? ((~ t) : t)) t (((f | (~ t)) | f) ? (t : f))";
+        assert_eq!(
+            parse_str(legacy).unwrap(),
+            parse_str(include_str!("../../example.bl")).unwrap()
+        );
+
+        // A parenthesised pair is only the branches when it is all that follows `?`
+        assert!(parse_str("t ? (t ? f : t)").is_err());
+        assert!(parse_str("t ? (f : t) | f").is_err());
+    }
+
+    #[test]
+    fn test_parse_bad_ternary_operator() {
+        // Stray `:` and `?` are rejected at parse time with a targeted message
+        let cases = [
+            ("t : f", "`:` without a preceding `?`"),
+            ("t : t : f", "`:` without a preceding `?`"),
+            ("t ? (f | t)", "`?` without a matching `:`"),
+            ("t ? f", "`?` without a matching `:`"),
+            ("t ? f ? t : f", "`?` without a matching `:`"),
+        ];
+        cases.iter().for_each(|&(input, message)| {
+            let pairs = fallible_parse(Rule::program, input).unwrap();
+            let error = parse_program(pairs).unwrap_err();
+            assert!(
+                format!("{}", error).contains(message),
+                "{:?} gave {}",
+                input,
+                error
+            );
+        });
     }
 
     #[test]
@@ -276,38 +798,1287 @@ mod tests {
         assert_eq!(value, Some(false));
 
         // Evaluate ternary operator
-        let expr = Expr::Binary {
-            op: BinOp::Condition,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Binary {
-                op: BinOp::Branch,
-                arg1: Arc::new(Expr::Value(false)),
-                arg2: Arc::new(Expr::Value(true)),
-            }),
+        let expr = Expr::Ternary {
+            cond: Arc::new(Expr::Value(true)),
+            then: Arc::new(Expr::Value(false)),
+            else_: Arc::new(Expr::Value(true)),
         };
         let value = evaluate_expr(expr);
         assert_eq!(value, Some(false));
     }
 
     #[test]
-    fn test_eval_bad_expr() {
-        // Fail to evaluate single condition expression
-        let expr = Expr::Binary {
-            op: BinOp::Condition,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Value(true)),
+    fn test_eval_expr_extended_binary() {
+        // Evaluate each extended operator over its full truth table
+        let table: [(BinOp, [bool; 4]); 5] = [
+            (BinOp::Xor, [false, true, true, false]),
+            (BinOp::Nand, [true, true, true, false]),
+            (BinOp::Nor, [true, false, false, false]),
+            (BinOp::Implies, [true, true, false, true]),
+            (BinOp::Iff, [true, false, false, true]),
+        ];
+        table.iter().for_each(|&(op, expected)| {
+            let rows = [(false, false), (false, true), (true, false), (true, true)];
+            rows.iter().zip(expected).for_each(|(&(a, b), expected)| {
+                let expr = Expr::Binary {
+                    op,
+                    arg1: Arc::new(Expr::Value(a)),
+                    arg2: Arc::new(Expr::Value(b)),
+                };
+                assert_eq!(evaluate_expr(expr), Some(expected));
+            });
+        });
+    }
+
+    #[test]
+    fn test_eval_expr_nary() {
+        // Evaluate n-ary AND and OR expressions
+        let expr = Expr::And(vec![
+            Expr::Value(true),
+            Expr::Value(true),
+            Expr::Value(false),
+        ]);
+        assert_eq!(evaluate_expr(expr), Some(false));
+        let expr = Expr::Or(vec![
+            Expr::Value(false),
+            Expr::Value(false),
+            Expr::Value(true),
+        ]);
+        assert_eq!(evaluate_expr(expr), Some(true));
+
+        // Empty n-ary nodes evaluate to their identity
+        assert_eq!(evaluate_expr(Expr::And(vec![])), Some(true));
+        assert_eq!(evaluate_expr(Expr::Or(vec![])), Some(false));
+    }
+
+    #[test]
+    fn test_trace_expr() {
+        let parse = |input: &str| parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+        let terms = |input: &str| {
+            trace_expr(&parse(input))
+                .iter()
+                .map(|step| format_expr(&step.term))
+                .collect::<Vec<String>>()
         };
-        let value = evaluate_expr(expr);
-        assert_eq!(value, None);
 
-        // Fail to evaluate single branch expression
-        let expr = Expr::Binary {
-            op: BinOp::Branch,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Value(true)),
+        // Operands reduce left to right before their operator
+        assert_eq!(terms("(t | f) & ~t"), vec!["(t & (~ t))", "(t & f)", "f"]);
+        // Only the chosen branch of a ternary is reduced
+        assert_eq!(
+            terms("t ^ f ? ~f : ~z"),
+            vec!["(t ? (~ f) : (~ z))", "(~ f)", "t"]
+        );
+        // N-ary nodes reduce once all their operands are values
+        let steps = trace_expr(&flatten_expr(&parse("(t -> f) & t & (f <-> f)")));
+        assert_eq!(
+            steps
+                .iter()
+                .map(|step| format_expr(&step.term))
+                .collect::<Vec<String>>(),
+            vec!["(f & t & (f <-> f))", "(f & t & t)", "f"]
+        );
+        // Values take no steps, and unbound variables get stuck
+        assert!(terms("t").is_empty());
+        assert!(terms("~x").is_empty());
+        assert_eq!(terms("(t & t) | x"), vec!["(t | x)"]);
+
+        // The final term of every trace is the evaluated value
+        [
+            "t ? (f ? t : f) : ~(t | f)",
+            "~t | (t !& f) ^ (t !| f) -> (t <-> f)",
+            "f & t & f | ~(t | t | t)",
+        ]
+        .iter()
+        .for_each(|&input| {
+            let expr = flatten_expr(&parse(input));
+            let steps = trace_expr(&expr);
+            assert_eq!(
+                steps.last().map(|step| step.term.clone()),
+                evaluate_expr(expr).map(Expr::Value)
+            );
+        });
+
+        // Steps are printed as text or as JSON events
+        let exprs = vec![parse("(t | f) & ~t")];
+        let mut text = Vec::new();
+        write_trace(&mut text, &exprs, None, TraceFormat::Text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "expr 0: ((t | f) & (~ t))\n  => (t & (~ t))\n  => (t & f)\n  => f\n"
+        );
+        let mut json = Vec::new();
+        write_trace(&mut json, &exprs, None, TraceFormat::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap().lines().next().unwrap(),
+            r#"{"expr":0,"step":1,"redex":"(t | f)","reduct":"t","term":"(t & (~ t))"}"#
+        );
+
+        // Traces of a row of assignments say which row they belong to
+        let exprs = vec![parse("t | f")];
+        let mut text = Vec::new();
+        write_trace(&mut text, &exprs, Some(2), TraceFormat::Text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "row 2, expr 0: (t | f)\n  => t\n"
+        );
+        let mut json = Vec::new();
+        write_trace(&mut json, &exprs, Some(2), TraceFormat::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap().trim_end(),
+            r#"{"row":2,"expr":0,"step":1,"redex":"(t | f)","reduct":"t","term":"t"}"#
+        );
+    }
+
+    #[test]
+    fn test_reduction_strategies() {
+        let parse = |input: &str| parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+        let terms = |input: &str, strategy: Strategy| {
+            let mut terms = Vec::new();
+            let mut term = parse(input);
+            while let Some(next) = step_with(&term, strategy) {
+                terms.push(format_expr(&next));
+                term = next;
+            }
+            terms
         };
-        let value = evaluate_expr(expr);
-        assert_eq!(value, None);
+
+        // Normal order chooses a branch before reducing it, applicative order reduces both
+        let input = "t ? ~t : (f | f)";
+        assert_eq!(
+            terms(input, Strategy::LeftmostOutermost),
+            vec!["(~ t)", "f"]
+        );
+        assert_eq!(
+            terms(input, Strategy::LeftmostInnermost),
+            vec!["(t ? f : (f | f))", "(t ? f : f)", "f"]
+        );
+        // Parallel steps contract every outermost redex at once
+        let input = "(t | f) & ~t";
+        assert_eq!(terms(input, Strategy::Parallel), vec!["(t & f)", "f"]);
+        let parallel = step_traced(&parse(input), Strategy::Parallel).unwrap();
+        assert_eq!(
+            parallel
+                .contractions
+                .iter()
+                .map(|contraction| format_expr(&contraction.redex))
+                .collect::<Vec<String>>(),
+            vec!["(t | f)", "(~ t)"]
+        );
+
+        // Values and terms stuck on unbound variables take no step
+        assert_eq!(step(&parse("f")), None);
+        assert_eq!(step(&parse("x & (y | z)")), None);
+        assert_eq!(
+            step(&parse("x & (t | z) & (t | f)")).map(|expr| format_expr(&expr)),
+            Some("((x & (t | z)) & t)".to_string())
+        );
+
+        // Every strategy agrees with the evaluator on closed terms
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut exprs: Vec<Expr> = [
+            "t ? (f ? t : f) : ~(t | f)",
+            "~t | (t !& f) ^ (t !| f) -> (t <-> f)",
+            "f & t & f | ~(t | t | t)",
+        ]
+        .iter()
+        .map(|input| parse(input))
+        .collect();
+        exprs.extend(
+            synthesize_program(
+                &[true, false, true, false, true, false, true, false],
+                5,
+                &mut rng,
+            )
+            .exprs,
+        );
+        exprs.iter().for_each(|expr| {
+            [expr.clone(), flatten_expr(expr)].iter().for_each(|expr| {
+                let value = evaluate_expr(expr.clone()).map(Expr::Value);
+                [
+                    Strategy::LeftmostOutermost,
+                    Strategy::LeftmostInnermost,
+                    Strategy::Parallel,
+                ]
+                .iter()
+                .for_each(|&strategy| assert_eq!(Some(reduce(expr, strategy)), value));
+            });
+        });
+    }
+
+    #[test]
+    fn test_eval_definitions() {
+        // Definitions are substituted into the expressions that use them
+        let input = "x = t & f  y = ~x  x | y  x  y ? x : y";
+        let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+        assert_eq!(
+            inline_definitions(&program)
+                .iter()
+                .map(format_expr)
+                .collect::<Vec<String>>(),
+            vec![
+                "((t & f) | (~ (t & f)))",
+                "(t & f)",
+                "((~ (t & f)) ? (t & f) : (~ (t & f)))"
+            ]
+        );
+        assert_eq!(evaluate_bits(program), Some(vec![true, false, false]));
+
+        // Free variables cannot be evaluated
+        let input = "x = t  x & y";
+        let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+        assert_eq!(evaluate_program(program), None);
+    }
+
+    #[test]
+    fn test_kleene_eval() {
+        let parse = |input: &str| input.parse::<Program>().unwrap();
+        use Kleene::{False as F, True as T, Unknown as U};
+
+        // `u` is a literal in every spelling, and no longer a variable name
+        assert_eq!(
+            parse("u U unknown uu").exprs[..3],
+            [Expr::Unknown, Expr::Unknown, Expr::Unknown]
+        );
+        assert_eq!(parse("uu").exprs[0], Expr::var("uu"));
+        assert!("u = t  u".parse::<Program>().is_err());
+        let program = parse("u ? ~u : unknown");
+        assert_eq!(
+            serde_json::from_str::<Program>(&serde_json::to_string(&program).unwrap()).unwrap(),
+            program
+        );
+        assert_eq!(
+            format_program_as(&program, Notation::Keyword),
+            "(unknown ? (not unknown) : unknown)"
+        );
+
+        // Strong Kleene connectives, agreeing with two-valued logic on t and f
+        assert_eq!([T.and(U), F.and(U), T.or(U), F.or(U), !U], [U, F, T, U, U]);
+        assert_eq!([U.ite(T, T), U.ite(T, F), T.ite(U, F)], [T, U, U]);
+        let ops = [
+            BinOp::Or,
+            BinOp::And,
+            BinOp::Xor,
+            BinOp::Nand,
+            BinOp::Nor,
+            BinOp::Implies,
+            BinOp::Iff,
+        ];
+        for op in ops {
+            for (v1, v2) in [(false, false), (false, true), (true, false), (true, true)] {
+                assert_eq!(
+                    apply_kleene_binop(op, v1.into(), v2.into()),
+                    apply_binop(op, v1, v2).into()
+                );
+            }
+        }
+
+        // Unbound variables are unknown, and determined outputs are reported as such
+        let program = parse("x = a & f  x  a | t  a  u ? t : t  u ^ t  ~u -> f");
+        assert_eq!(evaluate_kleene(&program, &Env::new()), [F, T, U, T, U, U]);
+        let env: Env = [("a".to_string(), true)].into_iter().collect();
+        assert_eq!(evaluate_kleene(&program, &env), [F, T, T, T, U, U]);
+        assert_eq!(
+            reduce(&program.exprs[5], Strategy::default()),
+            Expr::Unknown
+        );
+
+        // Two-valued evaluation rejects `u`
+        assert_eq!(
+            evaluate_bits_with(&parse("t & u"), &Env::new(), Limits::default()),
+            Err(EvalError::Unknown)
+        );
+        assert_eq!(truth_table(&Expr::Unknown), Err(EvalError::Unknown));
+
+        // A determined output holds however the unknown inputs turn out
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c"], &mut rng);
+            let env: Env = [("a".to_string(), rng.random())].into_iter().collect();
+            if let Some(value) = evaluate_kleene_expr(&expr, &env).known() {
+                for (b, c) in [(false, false), (false, true), (true, false), (true, true)] {
+                    let mut env = env.clone();
+                    env.extend([("b".to_string(), b), ("c".to_string(), c)]);
+                    assert_eq!(evaluate_expr_in(&expr, &env), Some(value));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserved_unknown() {
+        // Programs which used `u` or `unknown` as a name before three-valued
+        // logic no longer parse, or now mean the unknown value
+        let env: Env = [("a".to_string(), true), ("u".to_string(), true)].into();
+        for name in ["u", "U", "unknown", "Unknown", "UNKNOWN"] {
+            assert!(format!("{} = t  {}", name, name)
+                .parse::<Program>()
+                .is_err());
+            assert!(format!("x = {}  x", name).parse::<Program>().is_ok());
+            let program = format!("a & {}", name).parse::<Program>().unwrap();
+            assert_eq!(program.exprs, [Expr::var("a") & Expr::Unknown]);
+            assert_eq!(program.eval_in(&env), Err(EvalError::Unknown));
+        }
+
+        // Longer names starting with them are still variables
+        for name in ["uu", "u1", "u_", "unknowns", "_u"] {
+            let program = format!("{} = t  {}", name, name)
+                .parse::<Program>()
+                .unwrap();
+            assert_eq!(program.eval(), Ok(vec![true]));
+        }
+    }
+
+    #[test]
+    fn test_assignments() {
+        let env = |pairs: &[(&str, bool)]| -> Env {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect()
+        };
+        let expected = vec![
+            env(&[("a", true), ("b", false)]),
+            env(&[("a", false)]),
+            env(&[("a", true), ("b", true)]),
+        ];
+        let sources = [
+            ("a, b\nt, f\n0,\nTRUE, 1\n", AssignmentFormat::Csv),
+            (
+                "- {a: true, b: false}\n- {a: 0, b: null}\n- {a: ⊤, b: true}\n",
+                AssignmentFormat::Yaml,
+            ),
+            (
+                r#"[{"a": "t", "b": "f"}, {"a": false}, {"a": 1, "b": "true"}]"#,
+                AssignmentFormat::Json,
+            ),
+        ];
+        for (source, format) in sources {
+            assert_eq!(
+                read_assignments(source.as_bytes(), format).unwrap(),
+                expected
+            );
+        }
+        assert!(read_assignments(r#"[{"a": 2}]"#.as_bytes(), AssignmentFormat::Json).is_err());
+        assert_eq!(
+            AssignmentFormat::from_path("rows.YML"),
+            Some(AssignmentFormat::Yaml)
+        );
+
+        let vars = [
+            ("BL_a", "t"),
+            ("BL_", "f"),
+            ("PATH", "/bin"),
+            ("BL_b", "false"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        assert_eq!(
+            env_assignments("BL_", vars).unwrap(),
+            env(&[("a", true), ("b", false)])
+        );
+        assert!(env_assignments("", [("a".to_string(), "x".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_count_models() {
+        let parse = |input: &str| input.parse::<Program>().unwrap();
+        let counts = |models: Vec<BigUint>| -> Vec<u64> {
+            models.iter().map(|n| n.try_into().unwrap()).collect()
+        };
+        let program = parse("x = a ^ b ^ c  a & b  a | b  a | ~a  x  t  f");
+        assert_eq!(
+            counts(count_models(&program, None).unwrap()),
+            [1, 3, 2, 4, 1, 0]
+        );
+        let projection = ["a".to_string(), "z".to_string()];
+        assert_eq!(
+            counts(count_models(&program, Some(&projection)).unwrap()),
+            [2, 4, 4, 4, 4, 0]
+        );
+        assert_eq!(count_models(&parse("a & u"), None), Err(EvalError::Unknown));
+
+        // Counts far beyond a machine word, without enumerating them
+        let wide = (0..200)
+            .map(|i| format!("v{}", i))
+            .collect::<Vec<_>>()
+            .join(" ^ ");
+        assert_eq!(
+            count_models(&parse(&wide), None).unwrap(),
+            [BigUint::from(1u32) << 199]
+        );
+
+        // Agrees with enumeration, with and without projecting out `c`
+        let mut rng = StdRng::seed_from_u64(0);
+        let projection = ["a".to_string(), "b".to_string()];
+        for _ in 0..200 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c"], &mut rng);
+            let vars = free_vars(&expr);
+            let assignments = |names: &[String]| {
+                (0..1u32 << names.len())
+                    .map(|row| {
+                        let bits = names.iter().enumerate();
+                        bits.map(|(i, name)| (name.clone(), row & (1 << i) != 0))
+                            .collect::<Env>()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let models = assignments(&vars)
+                .iter()
+                .filter(|env| evaluate_expr_in(&expr, env) == Some(true))
+                .count();
+            let projected = assignments(&projection)
+                .iter()
+                .filter(|env| {
+                    [false, true].iter().any(|c| {
+                        let mut env = (*env).clone();
+                        env.insert("c".to_string(), *c);
+                        evaluate_expr_in(&expr, &env) == Some(true)
+                    })
+                })
+                .count();
+            let program = Program {
+                defs: Vec::new(),
+                exprs: vec![expr],
+            };
+            assert_eq!(
+                counts(count_models(&program, None).unwrap()),
+                [models as u64]
+            );
+            assert_eq!(
+                counts(count_models(&program, Some(&projection)).unwrap()),
+                [projected as u64]
+            );
+        }
+    }
+
+    #[test]
+    fn test_specialize() {
+        let parse = |input: &str| input.parse::<Program>().unwrap();
+        let env: Env = [("a".to_string(), true), ("b".to_string(), false)]
+            .into_iter()
+            .collect();
+        let program = parse("x = a & c  y = b | d  z = a -> b  x ^ y  z | e  c ? a : b  ~(~c)");
+        assert_eq!(
+            format_program(&specialize_program(&program, &env)),
+            "x = c y = d (x ^ y) e c c"
+        );
+
+        // A definition shadows an assignment of the same name
+        let program = parse("a = c  a & b");
+        assert_eq!(
+            format_program(&specialize_program(&program, &env)),
+            "a = c f"
+        );
+
+        // A program whose definitions all fold away keeps its last one
+        let program = parse("x = a  y = ~x & b");
+        let residual = specialize_program(&program, &env);
+        assert_eq!(format_program(&residual), "y = f");
+        assert_eq!(parse(&format_program(&residual)), residual);
+
+        // The residual agrees with the original wherever the rest is assigned
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c"], &mut rng);
+            let known: Env = [("a".to_string(), rng.random())].into_iter().collect();
+            let residual = specialize_expr(&expr, &known);
+            assert!(!free_vars(&residual).contains(&"a".to_string()));
+            for (b, c) in [(false, false), (false, true), (true, false), (true, true)] {
+                let mut env = known.clone();
+                env.extend([("b".to_string(), b), ("c".to_string(), c)]);
+                assert_eq!(
+                    evaluate_expr_in(&residual, &env),
+                    evaluate_expr_in(&expr, &env)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_limits() {
+        let parse =
+            |input: &str| parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+
+        // Limited evaluation agrees with inlining, and unbound variables are reported by name
+        let program = parse("x = t & f  y = ~x  x | y  x  y ? x : y");
+        assert_eq!(
+            evaluate_bits_with(&program, &Env::new(), Limits::default()),
+            Ok(evaluate_bits(program).unwrap())
+        );
+        let program = parse("x = t  x & y");
+        assert_eq!(
+            evaluate_bits_with(&program, &Env::new(), Limits::default()),
+            Err(EvalError::Unbound("y".to_string()))
+        );
+        let env: Env = [("y".to_string(), true)].into_iter().collect();
+        assert_eq!(
+            evaluate_bits_with(&program, &env, Limits::default()),
+            Ok(vec![true])
+        );
+
+        // Definitions are evaluated once, so doubling chains stay cheap
+        let input = (0..64).fold("x0 = t".to_string(), |acc, i| {
+            format!("{} x{} = x{} ^ x{}", acc, i + 1, i, i)
+        }) + " x64 ~x64";
+        let program = parse(&input);
+        let limits = Limits {
+            max_steps: Some(400),
+            ..Default::default()
+        };
+        assert_eq!(
+            evaluate_bits_with(&program, &Env::new(), limits),
+            Ok(vec![false, true])
+        );
+
+        // Repeated sub-expressions are computed once, even when written out twice
+        let repeated = (0..12).fold("t".to_string(), |acc, _| format!("({}) ^ ({})", acc, acc));
+        let limits = Limits {
+            max_steps: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            evaluate_bits_with(&parse(&repeated), &Env::new(), limits),
+            Ok(vec![false])
+        );
+
+        // Each limit reports where it tripped
+        let limits = Limits {
+            max_steps: Some(50),
+            ..Default::default()
+        };
+        let error = evaluate_bits_with(&program, &Env::new(), limits).unwrap_err();
+        assert_eq!(
+            error,
+            EvalError::ResourceExhausted {
+                limit: Limit::Steps(50),
+                expr: 0,
+                def: Some("x40".to_string()),
+                at: Expr::Var("x39".to_string()),
+            }
+        );
+        assert_eq!(
+            format!("{}", error),
+            "evaluation exceeded 50 steps in expression 0, in the definition of `x40`, at `x39`"
+        );
+
+        let program = parse("t  ~(t & (f | ~t))");
+        let limits = Limits {
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate_bits_with(&program, &Env::new(), limits),
+            Err(EvalError::ResourceExhausted {
+                limit: Limit::Depth(3),
+                expr: 1,
+                def: None,
+                ..
+            })
+        ));
+
+        let program = parse(&"t ".repeat(5000));
+        let limits = Limits {
+            timeout: Some(std::time::Duration::ZERO),
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate_bits_with(&program, &Env::new(), limits),
+            Err(EvalError::ResourceExhausted {
+                limit: Limit::Timeout(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_compile_expr() {
+        // Conjunctions short-circuit, and ternaries jump over the branch not taken
+        let expr = parse_expr(fallible_parse(Rule::expr, "a & ~b ? t : a ^ b").unwrap()).unwrap();
+        let code = compile(&expr);
+        assert_eq!(code.vars, vec!["a", "b"]);
+        assert_eq!(
+            code.ops,
+            vec![
+                Op::Load(0),
+                Op::JumpIfFalseOrPop(4),
+                Op::Load(1),
+                Op::Not,
+                Op::JumpIfFalse(7),
+                Op::Push(true),
+                Op::Jump(10),
+                Op::Load(0),
+                Op::Load(1),
+                Op::Binary(BinOp::Xor),
+            ]
+        );
+        assert_eq!(code.max_stack, 2);
+        assert!(code.eval(&[true, false]));
+        assert!(!code.eval(&[false, false]));
+        assert_eq!(code.eval_in(&Env::new()), None);
+
+        // The VM agrees with the tree walker on every assignment
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut vm = Vm::new();
+        for _ in 0..200 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c", "d"], &mut rng);
+            let code = compile(&expr);
+            for row in 0..1u32 << code.vars.len() {
+                let inputs: Vec<bool> = (0..code.vars.len()).map(|i| row >> i & 1 == 1).collect();
+                let env: Env = code.vars.iter().cloned().zip(inputs.clone()).collect();
+                assert_eq!(
+                    Some(vm.run(&code, &inputs)),
+                    evaluate_expr_in(&expr, &env),
+                    "{}",
+                    format_expr(&expr)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_eval() {
+        // Each bit of the input words is a separate assignment
+        let expr = parse_expr(fallible_parse(Rule::expr, "a ? b : ~b").unwrap()).unwrap();
+        let code = compile_batch(&expr);
+        assert_eq!(code.vars, vec!["a", "b"]);
+        assert_eq!(code.eval(&[[0b1100], [0b1010]]), [!0b0110]);
+
+        // Bit tables agree with the evaluator, across lanes and row by row
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c", "d"], &mut rng);
+            let code = compile_batch(&expr);
+            let inputs: Vec<[u64; 2]> = code
+                .vars
+                .iter()
+                .map(|_| [rng.random(), rng.random()])
+                .collect();
+            let output = code.eval(&inputs);
+            for bit in 0..128 {
+                let env: Env = code
+                    .vars
+                    .iter()
+                    .zip(&inputs)
+                    .map(|(var, words)| (var.clone(), words[bit / 64] >> (bit % 64) & 1 == 1))
+                    .collect();
+                assert_eq!(
+                    Some(output[bit / 64] >> (bit % 64) & 1 == 1),
+                    evaluate_expr_in(&expr, &env)
+                );
+            }
+
+            let table = truth_table(&expr).unwrap();
+            let bits = truth_table_bits(&expr).unwrap();
+            assert_eq!(bits.vars, table.vars);
+            assert_eq!(bits.rows(), table.rows.len() as u64);
+            for (row, (_, output)) in table.rows.iter().enumerate() {
+                assert_eq!(bits.get(row as u64), *output);
+            }
+            assert_eq!(
+                bits.count_true(),
+                table.rows.iter().filter(|(_, output)| *output).count() as u64
+            );
+        }
+
+        // Wide formulas are tabulated exhaustively
+        let vars: Vec<String> = (0..22).map(|i| format!("v{}", i)).collect();
+        let parity = parse_expr(fallible_parse(Rule::expr, &vars.join(" ^ ")).unwrap()).unwrap();
+        assert_eq!(truth_table_bits(&parity).unwrap().count_true(), 1 << 21);
+        let implication = format!("{} -> {}", vars.join(" & "), vars.join(" | "));
+        let tautology = parse_expr(fallible_parse(Rule::expr, &implication).unwrap()).unwrap();
+        assert_eq!(truth_table_bits(&tautology).unwrap().count_true(), 1 << 22);
+
+        // Too many variables or `u` is an error rather than a panic
+        let vars: Vec<String> = (0..64).map(|i| format!("v{}", i)).collect();
+        let wide = parse_expr(fallible_parse(Rule::expr, &vars.join(" | ")).unwrap()).unwrap();
+        assert_eq!(
+            truth_table_bits(&wide),
+            Err(EvalError::TooManyVars {
+                vars: 64,
+                max: MAX_BIT_TABLE_VARS
+            })
+        );
+        assert_eq!(truth_table_bits(&Expr::Unknown), Err(EvalError::Unknown));
+    }
+
+    #[test]
+    fn test_truth_table() {
+        // Tabulate an expression over its free variables in order of appearance
+        let expr = parse_expr(fallible_parse(Rule::expr, "b & ~a | b").unwrap()).unwrap();
+        assert_eq!(free_vars(&expr), vec!["b", "a"]);
+        let table = truth_table(&expr).unwrap();
+        assert_eq!(table.vars, vec!["b", "a"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                (vec![false, false], false),
+                (vec![false, true], false),
+                (vec![true, false], true),
+                (vec![true, true], true),
+            ]
+        );
+
+        // Closed expressions have a single row
+        let expr = parse_expr(fallible_parse(Rule::expr, "t ^ t").unwrap()).unwrap();
+        assert_eq!(truth_table(&expr).unwrap().rows, vec![(vec![], false)]);
+
+        // Too many variables is an error rather than an overflowing row count
+        for width in [MAX_TRUTH_TABLE_VARS + 1, 64, 100] {
+            let vars: Vec<String> = (0..width).map(|i| format!("v{}", i)).collect();
+            let expr = parse_expr(fallible_parse(Rule::expr, &vars.join(" & ")).unwrap()).unwrap();
+            assert_eq!(
+                truth_table(&expr),
+                Err(EvalError::TooManyVars {
+                    vars: width,
+                    max: MAX_TRUTH_TABLE_VARS
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_lsp_session() {
+        use serde_json::{json, Value};
+
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || serve(&server));
+        let request = |id: i32, method: &str, params: Value| -> Value {
+            let request = Request::new(id.into(), method.to_string(), params);
+            client.sender.send(request.into()).unwrap();
+            match client.receiver.recv().unwrap() {
+                Message::Response(response) => response.result.unwrap(),
+                message => panic!("expected a response, got {:?}", message),
+            }
+        };
+        let notify = |method: &str, params: Value| {
+            let notification = Notification::new(method.to_string(), params);
+            client.sender.send(notification.into()).unwrap();
+        };
+        let diagnostics = || match client.receiver.recv().unwrap() {
+            Message::Notification(notification) => notification.params["diagnostics"].clone(),
+            message => panic!("expected diagnostics, got {:?}", message),
+        };
+        let uri = "file:///example.bl";
+        let at = |line: u32, character: u32| json!({"textDocument": {"uri": uri}, "position": {"line": line, "character": character}});
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            json!({
+                "start": {"line": start.0, "character": start.1},
+                "end": {"line": end.0, "character": end.1}
+            })
+        };
+
+        let capabilities = request(1, "initialize", json!({"capabilities": {}}));
+        assert_eq!(capabilities["capabilities"]["hoverProvider"], json!(true));
+        notify("initialized", json!({}));
+
+        // Variables no earlier definition binds are reported when a document is opened
+        let text = "x = t & f\ny = ~x\nx | y\nz ? x : ⊤\n";
+        notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "languageId": "blambda", "version": 1, "text": text}}),
+        );
+        let opened = diagnostics();
+        assert_eq!(opened.as_array().unwrap().len(), 1);
+        assert_eq!(opened[0]["range"], range((3, 0), (3, 1)));
+        assert_eq!(opened[0]["severity"], json!(2));
+
+        // Hover shows the value of a closed sub-expression, or its truth table
+        let hover = request(2, "textDocument/hover", at(2, 2));
+        assert_eq!(
+            hover["contents"]["value"],
+            json!("```blambda\n(x | y)\n```\n= t")
+        );
+        assert_eq!(hover["range"], range((2, 0), (2, 5)));
+        let hover = request(3, "textDocument/hover", at(3, 0));
+        assert_eq!(
+            hover["contents"]["value"],
+            json!("```blambda\nz\n```\n| z | `z` |\n|---|---|\n| f | f |\n| t | t |")
+        );
+        let hover = request(4, "textDocument/hover", at(3, 8));
+        assert_eq!(hover["contents"]["value"], json!("```blambda\nt\n```\n= t"));
+        assert_eq!(hover["range"], range((3, 8), (3, 9)));
+        let hover = request(5, "textDocument/hover", at(0, 0));
+        assert_eq!(
+            hover["contents"]["value"],
+            json!("```blambda\n(t & f)\n```\n= f")
+        );
+
+        // Variables go to the definition of their name
+        let location = request(6, "textDocument/definition", at(2, 4));
+        assert_eq!(location["uri"], json!(uri));
+        assert_eq!(location["range"], range((1, 0), (1, 1)));
+        let location = request(7, "textDocument/definition", at(3, 0));
+        assert_eq!(location, Value::Null);
+
+        // Formatting replaces the document with its formatted program
+        let edits = request(
+            8,
+            "textDocument/formatting",
+            json!({"textDocument": {"uri": uri}, "options": {"tabSize": 4, "insertSpaces": true}}),
+        );
+        assert_eq!(
+            edits,
+            json!([{
+                "range": range((0, 0), (4, 0)),
+                "newText": "x = (t & f) y = (~ x) (x | y) (z ? x : t)\n"
+            }])
+        );
+
+        // Semantic tokens classify operators, literals and variables
+        let tokens = request(
+            9,
+            "textDocument/semanticTokens/full",
+            json!({"textDocument": {"uri": uri}}),
+        );
+        #[rustfmt::skip]
+        assert_eq!(
+            tokens["data"],
+            json!([
+                0, 0, 1, 2, 1,  0, 4, 1, 1, 0,  0, 2, 1, 0, 0,  0, 2, 1, 1, 0,
+                1, 0, 1, 2, 1,  0, 4, 1, 0, 0,  0, 1, 1, 2, 0,
+                1, 0, 1, 2, 0,  0, 2, 1, 0, 0,  0, 2, 1, 2, 0,
+                1, 0, 1, 2, 0,  0, 2, 1, 0, 0,  0, 2, 1, 2, 0,  0, 2, 1, 0, 0,  0, 2, 1, 1, 0
+            ])
+        );
+
+        // Parse errors are reported where they occur as the document changes
+        notify(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 2}, "contentChanges": [{"text": "t\nt : f"}]}),
+        );
+        let changed = diagnostics();
+        assert_eq!(changed.as_array().unwrap().len(), 1);
+        assert_eq!(changed[0]["range"], range((1, 2), (1, 3)));
+        assert_eq!(changed[0]["severity"], json!(1));
+        assert!(changed[0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("`:` without a preceding `?`"));
+
+        // Hover tabulates the Kleene value when `u` leaves no truth table
+        notify(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 3}, "contentChanges": [{"text": "a = u | f\na & c\n"}]}),
+        );
+        assert_eq!(diagnostics().as_array().unwrap().len(), 1);
+        let hover = request(10, "textDocument/hover", at(1, 0));
+        assert_eq!(hover["contents"]["value"], json!("```blambda\na\n```\n= u"));
+        let hover = request(11, "textDocument/hover", at(1, 2));
+        assert_eq!(
+            hover["contents"]["value"],
+            json!("```blambda\n(a & c)\n```\n| c | `(a & c)` |\n|---|---|\n| f | f |\n| t | u |")
+        );
+
+        // Variables go to the last definition before them, not to a later one
+        notify(
+            "textDocument/didChange",
+            json!({"textDocument": {"uri": uri, "version": 4}, "contentChanges": [{"text": "b = t\nb = ~b\nb\nc\nc = b\n"}]}),
+        );
+        diagnostics();
+        let location = request(12, "textDocument/definition", at(1, 5));
+        assert_eq!(location["range"], range((0, 0), (0, 1)));
+        let location = request(13, "textDocument/definition", at(1, 0));
+        assert_eq!(location["range"], range((1, 0), (1, 1)));
+        let location = request(14, "textDocument/definition", at(2, 0));
+        assert_eq!(location["range"], range((1, 0), (1, 1)));
+        let location = request(15, "textDocument/definition", at(3, 0));
+        assert_eq!(location, Value::Null);
+
+        request(16, "shutdown", Value::Null);
+        notify("exit", Value::Null);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let mut session = Session::new();
+        let mut reply = |line: &str| match session.handle_line(line) {
+            Ok(Reply::Text(text)) => text,
+            Ok(Reply::Quit) => "<quit>".to_string(),
+            Err(e) => format!("<error> {}", e),
+        };
+
+        // Definitions persist across lines and can be redefined
+        assert_eq!(reply("x = t & f"), "x = (t & f)");
+        assert_eq!(reply("x | ~x"), "(x | (~ x)) => t");
+        assert_eq!(reply("y = x & t"), "y = (x & t)");
+        assert_eq!(reply("x = t"), "x = t");
+        assert_eq!(reply("y ^ x"), "(y ^ x) => t");
+        assert_eq!(reply(":defs"), "x = (t & f)\ny = (x & t)\nx = t");
+
+        // A redefinition only affects later lines, and may refer to the old value
+        assert_eq!(reply("x = ~x"), "x = (~ x)");
+        assert_eq!(reply("x"), "x => f");
+        assert_eq!(reply("y"), "y => f");
+
+        // Within a line, an expression only sees the definitions before it
+        assert_eq!(reply("v = f"), "v = f");
+        assert_eq!(reply("v  v = t  v"), "v => f\nv = t\nv => t");
+        assert!(reply("z  z = t").contains("unbound variables z"));
+
+        // Unbound variables are reported rather than evaluated, with or without `u`
+        assert!(reply("x & z").contains("unbound variables z"));
+        assert!(reply("u | z").contains("unbound variables z"));
+        assert!(reply("x & u & z").contains("unbound variables z"));
+        assert_eq!(reply("x | u"), "(x | u) => u");
+        assert_eq!(reply("x & u"), "(x & u) => f");
+
+        // Meta-commands
+        assert_eq!(reply(":fmt a & b | c"), "((a & b) | c)");
+        assert_eq!(reply(":ast ~a"), "op: not\narg: a\n");
+        assert_eq!(reply(":ast"), "AST display on");
+        assert_eq!(reply("~t"), "(~ t) => f\nop: not\narg: true");
+        assert_eq!(reply(":table x & a"), "a | (x & a)\nf | f\nt | f");
+        let wide = (0..11)
+            .map(|i| format!("v{}", i))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        assert!(reply(&format!(":table {}", wide)).contains("cannot tabulate 11 free variables"));
+        assert!(reply(":table u & a")
+            .contains("cannot tabulate (u & a): `u` is neither t nor f, so it has no truth table"));
+        for line in [
+            ":fmt t & f garbage )))",
+            ":table a b",
+            ":ast x = t",
+            ":fmt t &",
+        ] {
+            assert!(reply(line).starts_with("<error>"), "{} was accepted", line);
+        }
+        assert!(reply(":nope").contains("unknown command `:nope`"));
+        assert_eq!(reply(":quit"), "<quit>");
+    }
+
+    #[test]
+    fn test_expr_arena() {
+        let parse = |input: &str| parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+
+        // Repeated sub-expressions become a single node
+        let mut arena = ExprArena::new();
+        let id = arena.add_expr(&parse("(a & b) | (a & b)"));
+        assert_eq!(arena.len(), 4);
+        let Node::Binary { arg1, arg2, .. } = arena.node(id).clone() else {
+            panic!("expected a binary node");
+        };
+        assert_eq!(arg1, arg2);
+
+        // Structurally equal expressions get equal ids, however they were built
+        assert_eq!(arena.add_expr(&parse("a&b|(a&b)")), id);
+        assert_eq!(arena.add_expr(&parse("a & b")), arg1);
+        assert_ne!(arena.add_expr(&parse("b & a")), arg1);
+
+        // Expressions survive the round trip through the arena
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c"], &mut rng);
+            let id = arena.add_expr(&expr);
+            assert_eq!(arena.to_expr(id), expr);
+        }
+
+        // Inlining shares each definition's body rather than copying it
+        let input = (0..64).fold("x0 = a".to_string(), |acc, i| {
+            format!("{} x{} = x{} ^ x{}", acc, i + 1, i, i)
+        }) + " x64 ~x64";
+        let program = parse_program(fallible_parse(Rule::program, &input).unwrap()).unwrap();
+        let dag = ProgramDag::inlined(&program);
+        assert_eq!(dag.arena.len(), 66);
+        assert_eq!(dag.arena.reachable(dag.exprs[1]), 66);
+        assert_eq!(dag.defs[64], ("x64".to_string(), dag.exprs[0]));
+        let inlined = ExprArena::new().add_expr(&inline_definitions(&program)[0]);
+        assert_eq!(inlined.index(), 64);
+
+        // Programs convert to and from their DAG
+        let dag = ProgramDag::from_program(&program);
+        assert_eq!(dag.to_program(), program);
+        assert_eq!(dag.arena.node(dag.exprs[0]), &Node::Var("x64".to_string()));
+    }
+
+    #[test]
+    fn test_memoized_eval() {
+        let parse =
+            |input: &str| parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+
+        // Each distinct sub-expression is computed once per assignment
+        let input = (0..64).fold("x0 = a".to_string(), |acc, i| {
+            format!("{} x{} = x{} ^ x{}", acc, i + 1, i, i)
+        }) + " x64 ~x64 x0";
+        let dag = ProgramDag::inlined(&parse(&input));
+        let mut evaluator = MemoEvaluator::new(&dag);
+        for a in [false, true] {
+            let env: Env = [("a".to_string(), a)].into_iter().collect();
+            assert_eq!(evaluator.eval_all(&env), Ok(vec![false, true, a]));
+        }
+        assert_eq!(
+            evaluator.stats,
+            CacheStats {
+                hits: 2 * 66,
+                misses: 2 * 66
+            }
+        );
+        assert_eq!(
+            evaluator.eval_all(&Env::new()),
+            Err(EvalError::Unbound("a".to_string()))
+        );
+
+        // Memoized evaluation agrees with the tree walker
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let expr = random_expr(rng.random_range(0..6), &["a", "b", "c"], &mut rng);
+            let program = Program {
+                defs: Vec::new(),
+                exprs: vec![expr.clone()],
+            };
+            let dag = ProgramDag::inlined(&program);
+            let mut evaluator = MemoEvaluator::new(&dag);
+            for row in 0..8 {
+                let env: Env = ["a", "b", "c"]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, var)| (var.to_string(), row >> i & 1 == 1))
+                    .collect();
+                assert_eq!(
+                    evaluator.eval_all(&env).unwrap(),
+                    vec![evaluate_expr_in(&expr, &env).unwrap()]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ast_traversal() {
+        let parse = |input: &str| input.parse::<Expr>().unwrap();
+
+        // Visit sees nodes parents first, operands left to right
+        struct Order(Vec<String>);
+        impl Visit for Order {
+            fn visit_var(&mut self, name: &str) {
+                self.0.push(name.to_string());
+            }
+            fn visit_binary(&mut self, op: BinOp, _: &Expr, _: &Expr) {
+                self.0.push(format!("{:?}", op));
+            }
+        }
+        let mut order = Order(Vec::new());
+        order.walk(&flatten_expr(&parse("a & (b ? c : d) !& ~e | g")));
+        assert_eq!(order.0, ["Nand", "a", "b", "c", "d", "e", "g"]);
+
+        // VisitMut revisits what it puts in place, leaving shared operands intact
+        struct Rewrite;
+        impl VisitMut for Rewrite {
+            fn visit_var_mut(&mut self, name: &mut String) {
+                name.make_ascii_uppercase();
+            }
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                match expr {
+                    Expr::Binary {
+                        op: BinOp::Nand,
+                        arg1,
+                        arg2,
+                    } => *expr = !(arg1.as_ref().clone() & arg2.as_ref().clone()),
+                    Expr::Var(name) => self.visit_var_mut(name),
+                    _ => (),
+                }
+            }
+        }
+        let original = parse("a !& (b !& c) | a");
+        let mut rewritten = original.clone();
+        Rewrite.walk_mut(&mut rewritten);
+        assert_eq!(rewritten, parse("~(A & ~(B & C)) | A"));
+        assert_eq!(original, parse("a !& (b !& c) | a"));
+
+        // The unknown value has a hook of its own, by which it can be replaced
+        struct Resolve(bool);
+        impl VisitMut for Resolve {
+            fn visit_unknown_mut(&mut self, expr: &mut Expr) {
+                *expr = Expr::Value(self.0);
+            }
+        }
+        let mut resolved = parse("a & u | ~unknown");
+        Resolve(false).walk_mut(&mut resolved);
+        assert_eq!(resolved, parse("a & f | ~f"));
+
+        // Fold rebuilds bottom-up, without recursion
+        struct FoldNot;
+        impl Fold for FoldNot {
+            fn fold_unary(&mut self, op: UnOp, arg: Expr) -> Expr {
+                match arg {
+                    Expr::Value(v) => Expr::Value(!v),
+                    arg => Expr::Unary {
+                        op,
+                        arg: Arc::new(arg),
+                    },
+                }
+            }
+        }
+        assert_eq!(
+            FoldNot.fold(&parse("~(~(~t)) & ~a ? ~f : x")),
+            parse("f & ~a ? t : x")
+        );
+        let deep = (0..5_000).fold(Expr::Value(true), |expr, _| !expr);
+        assert_eq!(FoldNot.fold(&deep), Expr::Value(true));
+        let mut count = Order(Vec::new());
+        count.walk(&(0..5_000).fold(Expr::var("x"), |expr, _| expr ^ Expr::var("y")));
+        assert_eq!(count.0.len(), 10_001);
+    }
+
+    #[test]
+    fn test_flatten_expr() {
+        // Chains of the same operator collapse into one n-ary node
+        let input = "t & f & t & (f & t)";
+        let expr = parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+        assert_eq!(
+            flatten_expr(&expr),
+            Expr::And(vec![
+                Expr::Value(true),
+                Expr::Value(false),
+                Expr::Value(true),
+                Expr::Value(false),
+                Expr::Value(true),
+            ])
+        );
+
+        // Alternating operators nest without merging
+        let input = "t & f | t & (f | ~(t | f))";
+        let expr = parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+        assert_eq!(
+            flatten_expr(&expr),
+            Expr::And(vec![
+                Expr::Or(vec![
+                    Expr::And(vec![Expr::Value(true), Expr::Value(false)]),
+                    Expr::Value(true),
+                ]),
+                Expr::Or(vec![
+                    Expr::Value(false),
+                    Expr::Unary {
+                        op: UnOp::Not,
+                        arg: Arc::new(Expr::Or(vec![Expr::Value(true), Expr::Value(false)])),
+                    },
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_flatten_long_chain() {
+        // Flattening a long chain yields one node and evaluates without deep recursion
+        let input = vec!["t"; 5000].join(" & ");
+        let program = parse_program(fallible_parse(Rule::program, &input).unwrap()).unwrap();
+        let flattened = flatten_program(&program);
+        match &flattened.exprs[..] {
+            [Expr::And(args)] => assert_eq!(args.len(), 5000),
+            exprs => panic!("expected a single n-ary AND, got {:?}", exprs),
+        }
+        assert_eq!(evaluate_program(flattened), Some(BigUint::from(1u32)));
+    }
+
+    #[test]
+    fn test_pack_bits_order() {
+        // The first expression is the most significant bit by default
+        let program = parse_program(fallible_parse(Rule::program, "t t f").unwrap()).unwrap();
+        assert_eq!(evaluate_program(program.clone()), Some(BigUint::from(6u32)));
+        let bits = evaluate_bits(program).unwrap();
+        assert_eq!(bits, vec![true, true, false]);
+        assert_eq!(pack_bits(&bits, BitOrder::Msb), BigUint::from(6u32));
+
+        // ...and the least significant bit when requested
+        assert_eq!(pack_bits(&bits, BitOrder::Lsb), BigUint::from(3u32));
+    }
+
+    #[test]
+    fn test_pack_bytes() {
+        // Bytes are padded to one per eight expressions
+        let bits = [true, false, false, false, false, false, false, false, true];
+        assert_eq!(pack_bytes(&bits, BitOrder::Msb), vec![0x01, 0x01]);
+        assert_eq!(pack_bytes(&bits, BitOrder::Lsb), vec![0x01, 0x01]);
+        let bits = [false, false, true];
+        assert_eq!(pack_bytes(&bits, BitOrder::Msb), vec![0x01]);
+        assert_eq!(pack_bytes(&bits, BitOrder::Lsb), vec![0x04]);
+
+        // The first byte holds the first expressions in either order
+        let bits = [[true; 4], [false; 4], [false; 4], [true; 4]].concat();
+        assert_eq!(pack_bytes(&bits, BitOrder::Msb), vec![0xf0, 0x0f]);
+        assert_eq!(pack_bytes(&bits, BitOrder::Lsb), vec![0x0f, 0xf0]);
+    }
+
+    #[test]
+    fn test_encode_bits() {
+        // Encode the same truth values in every shape
+        let bits = [
+            true, true, false, false, false, false, false, false, false, true,
+        ];
+        let cases = [
+            (Encoding::Decimal, BitOrder::Msb, "769"),
+            (Encoding::Decimal, BitOrder::Lsb, "515"),
+            (Encoding::Hex, BitOrder::Msb, "301"),
+            (Encoding::Octal, BitOrder::Msb, "1401"),
+            (Encoding::Binary, BitOrder::Msb, "1100000001"),
+            (Encoding::Binary, BitOrder::Lsb, "1000000011"),
+            (Encoding::Base64, BitOrder::Msb, "AwE="),
+            (Encoding::Bits, BitOrder::Lsb, "t t f f f f f f f t"),
+        ];
+        cases.iter().for_each(|&(encoding, order, expected)| {
+            let encoded = encode_bits(&bits, order, encoding);
+            assert_eq!(String::from_utf8(encoded).unwrap(), expected);
+        });
+
+        // Binary keeps leading zeros, one digit per expression
+        let encoded = encode_bits(&[false, false, true], BitOrder::Msb, Encoding::Binary);
+        assert_eq!(encoded, b"001");
+
+        // Raw emits the packed bytes untouched
+        let encoded = encode_bits(&bits, BitOrder::Msb, Encoding::Raw);
+        assert_eq!(encoded, vec![0x03, 0x01]);
+    }
+
+    #[test]
+    fn test_unpack_bits() {
+        // Unpacking inverts packing in either bit order
+        let value = BigUint::from(769u32);
+        [BitOrder::Msb, BitOrder::Lsb].iter().for_each(|&order| {
+            let bits = unpack_bits(&value, 0, order);
+            assert_eq!(bits.len(), 10);
+            assert_eq!(pack_bits(&bits, order), value);
+        });
+
+        // Narrow values are padded to the requested width, and zero is one bit
+        let bits = unpack_bits(&BigUint::from(1u32), 3, BitOrder::Msb);
+        assert_eq!(bits, vec![false, false, true]);
+        let bits = unpack_bits(&BigUint::from(1u32), 3, BitOrder::Lsb);
+        assert_eq!(bits, vec![true, false, false]);
+        assert_eq!(
+            unpack_bits(&BigUint::from(0u32), 0, BitOrder::Msb),
+            vec![false]
+        );
+
+        // Unpacking bytes inverts packing bytes
+        let bytes = vec![0x48, 0x69, 0x00];
+        [BitOrder::Msb, BitOrder::Lsb].iter().for_each(|&order| {
+            let bits = unpack_bytes(&bytes, order);
+            assert_eq!(bits.len(), 24);
+            assert_eq!(pack_bytes(&bits, order), bytes);
+        });
+    }
+
+    #[test]
+    fn test_synthesize_program() {
+        // Synthesized programs evaluate back to the requested value at any depth
+        let value = BigUint::parse_bytes(b"123456789012345678901234567890", 10).unwrap();
+        let bits = unpack_bits(&value, 0, BitOrder::Msb);
+        (0..5u64).for_each(|seed| {
+            (0..4).for_each(|depth| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let program = synthesize_program(&bits, depth, &mut rng);
+                assert_eq!(evaluate_program(program), Some(value.clone()));
+            });
+        });
+
+        // Depth zero yields bare literals
+        let mut rng = StdRng::seed_from_u64(0);
+        let program = synthesize_program(&[true, false, true], 0, &mut rng);
+        assert_eq!(format_program(&program), "t f t");
+
+        // The same seed yields the same program
+        let program1 = synthesize_program(&bits, 3, &mut StdRng::seed_from_u64(7));
+        let program2 = synthesize_program(&bits, 3, &mut StdRng::seed_from_u64(7));
+        assert_eq!(program1, program2);
     }
 
     #[test]
@@ -353,28 +2124,68 @@ mod tests {
         };
         let formatted = format_expr(&expr);
         assert_eq!(formatted, "(t & f)");
+
+        // Format binary XOR expression
+        let expr = Expr::Binary {
+            op: BinOp::Xor,
+            arg1: Arc::new(Expr::Value(true)),
+            arg2: Arc::new(Expr::Value(false)),
+        };
+        let formatted = format_expr(&expr);
+        assert_eq!(formatted, "(t ^ f)");
+
+        // Format binary IMPLIES expression
+        let expr = Expr::Binary {
+            op: BinOp::Implies,
+            arg1: Arc::new(Expr::Value(true)),
+            arg2: Arc::new(Expr::Value(false)),
+        };
+        let formatted = format_expr(&expr);
+        assert_eq!(formatted, "(t -> f)");
+    }
+
+    #[test]
+    fn test_format_expr_nary() {
+        // Format n-ary AND and OR expressions
+        let expr = Expr::And(vec![
+            Expr::Value(true),
+            Expr::Value(false),
+            Expr::Value(true),
+        ]);
+        assert_eq!(format_expr(&expr), "(t & f & t)");
+        let expr = Expr::Or(vec![Expr::Value(true), Expr::Value(false)]);
+        assert_eq!(format_expr_as(&expr, Notation::Keyword), "(true or false)");
+
+        // Degenerate n-ary nodes format as their identity or sole operand
+        assert_eq!(format_expr(&Expr::And(vec![])), "t");
+        assert_eq!(format_expr(&Expr::Or(vec![Expr::Value(true)])), "t");
+
+        // Formatted n-ary nodes parse back to the same flattened expression
+        let input = "(t & f & t) | f | ~(t | t)";
+        let expr = flatten_expr(&parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap());
+        let formatted = format_expr(&expr);
+        assert_eq!(formatted, "((t & f & t) | f | (~ (t | t)))");
+        let reparsed = parse_expr(fallible_parse(Rule::expr, &formatted).unwrap()).unwrap();
+        assert_eq!(flatten_expr(&reparsed), expr);
     }
 
     #[test]
     fn test_format_expr_ternary() {
         // Format ternary operator
-        let expr = Expr::Binary {
-            op: BinOp::Condition,
-            arg1: Arc::new(Expr::Value(true)),
-            arg2: Arc::new(Expr::Binary {
-                op: BinOp::Branch,
-                arg1: Arc::new(Expr::Value(false)),
-                arg2: Arc::new(Expr::Value(true)),
-            }),
+        let expr = Expr::Ternary {
+            cond: Arc::new(Expr::Value(true)),
+            then: Arc::new(Expr::Value(false)),
+            else_: Arc::new(Expr::Value(true)),
         };
         let formatted = format_expr(&expr);
-        assert_eq!(formatted, "(t ? (f : t))");
+        assert_eq!(formatted, "(t ? f : t)");
     }
 
     #[test]
     fn test_format_program() {
         // Test format singleton program
         let program = Program {
+            defs: vec![],
             exprs: vec![Expr::Binary {
                 op: BinOp::Or,
                 arg1: Arc::new(Expr::Value(true)),
@@ -386,6 +2197,7 @@ mod tests {
 
         // Test format program with multiple expressions
         let program = Program {
+            defs: vec![],
             exprs: vec![
                 Expr::Binary {
                     op: BinOp::Or,
@@ -403,13 +2215,58 @@ mod tests {
         assert_eq!(formatted, "(t | f) (t & f)");
     }
 
+    #[test]
+    fn test_format_expr_notation() {
+        // Format the same expression in each notation
+        let input = "~t & f | t ^ f -> t !& f <-> t !| f";
+        let expr = parse_expr(fallible_parse(Rule::expr, input).unwrap()).unwrap();
+        assert_eq!(
+            format_expr_as(&expr, Notation::Ascii),
+            "((((((~ t) & f) | t) ^ f) -> (t !& f)) <-> (t !| f))"
+        );
+        assert_eq!(
+            format_expr_as(&expr, Notation::Unicode),
+            "((((((¬ ⊤) ∧ ⊥) ∨ ⊤) ⊕ ⊥) → (⊤ ↑ ⊥)) ↔ (⊤ ↓ ⊥))"
+        );
+        assert_eq!(
+            format_expr_as(&expr, Notation::Keyword),
+            "((((((not true) and false) or true) xor false) implies (true nand false)) iff (true nor false))"
+        );
+    }
+
+    #[test]
+    fn test_parse_format_notation_commutes() {
+        // Formatting in any notation and parsing back yields the same program
+        let input = "(t ? f : t) (~ f <-> t) (t -> f ^ t !| f)";
+        let program = parse_program(fallible_parse(Rule::program, input).unwrap()).unwrap();
+        [Notation::Ascii, Notation::Unicode, Notation::Keyword]
+            .iter()
+            .for_each(|&notation| {
+                let formatted = format_program_as(&program, notation);
+                let pairs = fallible_parse(Rule::program, &formatted).unwrap();
+                assert_eq!(parse_program(pairs).unwrap(), program);
+            });
+    }
+
     #[test]
     fn test_parse_format_expr_commutes() {
         // Test that parsing and formatting an expression commutes
-        let inputs = vec!["t", "f", "(~ t)", "(f | f)", "(t & t)", "(t ? (f : t))"];
+        let inputs = [
+            "t",
+            "f",
+            "(~ t)",
+            "(f | f)",
+            "(t & t)",
+            "(t ^ f)",
+            "(t !& t)",
+            "(f !| f)",
+            "(t -> f)",
+            "(f <-> f)",
+            "(t ? f : t)",
+        ];
         inputs.iter().for_each(|&input| {
             let pairs = fallible_parse(Rule::expr, input).unwrap();
-            let expr = parse_expr(pairs);
+            let expr = parse_expr(pairs).unwrap();
             let formatted = format_expr(&expr);
             assert_eq!(formatted, input);
         });
@@ -418,14 +2275,10 @@ mod tests {
     #[test]
     fn test_parse_format_program_commutes() {
         // Test that parsing and formatting a program commutes
-        let inputs = vec![
-            "(t | f)",
-            "(t | f) (t & f)",
-            "(t | f) (t & f) (t ? (f : t))",
-        ];
+        let inputs = ["(t | f)", "(t | f) (t & f)", "(t | f) (t & f) (t ? f : t)"];
         inputs.iter().for_each(|&input| {
             let pairs = fallible_parse(Rule::program, input).unwrap();
-            let program = parse_program(pairs);
+            let program = parse_program(pairs).unwrap();
             let formatted = format_program(&program);
             assert_eq!(formatted, input);
         });
@@ -0,0 +1,155 @@
+use std::process::{Command, Output};
+
+/// Runs the `blambda` binary with the given arguments.
+fn blambda(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_blambda"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Writes `contents` to a fresh file in the temporary directory.
+fn temp_file(name: &str, contents: &str) -> String {
+    let path = std::env::temp_dir().join(format!("blambda-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_trace_file() {
+    // A bare `--trace` does not swallow the file argument that follows it
+    let path = temp_file("trace.bl", "(t | f) & ~t");
+    let output = blambda(&["eval", "--trace", &path]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "expr 0: ((t | f) & (~ t))\n  => (t & (~ t))\n  => (t & f)\n  => f\n"
+    );
+
+    let output = blambda(&["eval", "--trace=json", &path]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr).unwrap().lines().count(), 3);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_trace_refuses_limits() {
+    // Tracing prints every term in full, which the limits cannot bound, so
+    // this chain of definitions must be refused rather than expanded
+    let chain = (1..40).fold("x0 = t".to_string(), |acc, i| {
+        format!("{} x{} = x{} & x{}", acc, i, i - 1, i - 1)
+    }) + " x39";
+    for limit in ["--max-steps=1000", "--max-depth=10", "--timeout=100"] {
+        let output = blambda(&["eval", limit, "--trace", "-s", &chain]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("cannot be used with"));
+    }
+    let output = blambda(&["eval", "--max-steps=1000", "-s", &chain]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+}
+
+#[test]
+fn test_set_names() {
+    // `--set` takes only names a program could refer to
+    for command in ["eval", "specialize"] {
+        for name in ["1x", "a b", "a&b", "t", "U", ""] {
+            let set = format!("--set={}=t", name);
+            let output = blambda(&[command, &set, "-s", "a"]);
+            assert_eq!(output.status.code(), Some(2));
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("is not a variable name"));
+        }
+        let output = blambda(&[command, "--set", " a_1 =t", "-s", "a_1"]);
+        assert!(output.status.success());
+    }
+}
+
+#[test]
+fn test_specialize_everything() {
+    // Folding every definition away still prints a program
+    let output = blambda(&["specialize", "--set", "a=t", "-s", "x = a  y = ~x"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "y = f\n");
+}
+
+#[test]
+fn test_trace_rows() {
+    // Each row of assignments is traced with its own values
+    let path = temp_file("trace-rows.csv", "a,b\nt,f\nf,f\n");
+    let output = blambda(&["eval", "--trace", "--assignments", &path, "-s", "a | b"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n0\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "row 1, expr 0: (t | f)\n  => t\nrow 2, expr 0: (f | f)\n  => f\n"
+    );
+    std::fs::remove_file(path).unwrap();
+
+    // So are assignments made with --set alone
+    let output = blambda(&["eval", "--trace", "--set", "a=t", "-s", "a & t"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "expr 0: (t & t)\n  => t\n"
+    );
+}
+
+#[test]
+fn test_unbound_row() {
+    // A row leaving a variable unbound fails by row number, printing no rows
+    let path = temp_file("unbound-rows.yaml", "- a: t\n  b: f\n- a: t\n");
+    let output = blambda(&["eval", "--assignments", &path, "-s", "a & b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("row 2: `b` is not defined"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_raw_rows() {
+    // Raw bytes have no separator, so only a single row may be written raw
+    let path = temp_file("raw-rows.csv", "a\nt\nf\n");
+    let output = blambda(&[
+        "eval",
+        "--encoding",
+        "raw",
+        "--assignments",
+        &path,
+        "-s",
+        "a",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'--encoding raw' cannot be used with more than one row"));
+    std::fs::write(&path, "a\nt\n").unwrap();
+    let output = blambda(&[
+        "eval",
+        "--encoding",
+        "raw",
+        "--assignments",
+        &path,
+        "-s",
+        "a",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_forward_reference() {
+    // An expression cannot use a definition that follows it
+    let output = blambda(&["eval", "-s", "a  a = t"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("`a` is used before its definition"));
+}